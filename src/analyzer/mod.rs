@@ -0,0 +1,6 @@
+/// Something noteworthy found on a page
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub kind: String,
+    pub detail: String,
+}
@@ -1,3 +1,4 @@
+use std::net::SocketAddr;
 use std::path::PathBuf;
 
 use clap::*;
@@ -6,13 +7,339 @@ use clap::*;
 #[command(author = "Mihail Kovachev", version, about, long_about = None)]
 pub struct Args {
 
-    #[arg(short = 't', long = "targets", value_name = "Targets File", help = "The target hosts")]
-    pub targets: PathBuf,
+    #[command(subcommand)]
+    pub command: Command,
 
-    #[arg(short = 's', long = "crawl-subdomains", default_value_t = false, help = "Whether to also crawl subdomains of the targets as they are found.")]
+    #[command(flatten)]
+    pub logging: LogArgs
+
+}
+
+#[derive(clap::Args, Debug)]
+pub struct LogArgs {
+
+    #[arg(long = "log-level", value_name = "Level", global = true, default_value = "info", help = "The most verbose level of the logs")]
+    pub level: LogLevel,
+
+    #[arg(long = "log-filter", value_name = "Directives", global = true, help = "Per-module log levels, such as 'cherna_vdovitsa::crawler=debug,hyper=warn'. Falls back to RUST_LOG")]
+    pub filter: Option<String>,
+
+    #[arg(long = "log-file", value_name = "Log File", global = true, help = "Also write the logs to this file, as one JSON object per line")]
+    pub file: Option<PathBuf>
+
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct CrawlArgs {
+
+    #[arg(short = 'c', long = "config", value_name = "Config File", help = "A TOML configuration file with the crawl settings and any custom profiles")]
+    pub config: Option<PathBuf>,
+
+    #[arg(short = 'P', long = "profile", value_name = "Profile", help = "A profile to apply over the configuration file: stealthy, aggressive, or one defined in the configuration file")]
+    pub profile: Option<String>,
+
+    #[arg(short = 't', long = "targets", value_name = "Targets File", help = "A file of targets, one per line: URLs, domain names, IP addresses, host:port pairs or CIDR ranges. '-' reads stdin, and the option can be repeated")]
+    pub targets: Vec<PathBuf>,
+
+    #[arg(short = 'x', long = "exclude", value_name = "Exclusions File", help = "A file of hosts never to crawl, in the same format as the targets")]
+    pub exclude: Option<PathBuf>,
+
+    #[arg(long = "scope", value_name = "Scope File", help = "Include and exclude rules every crawled URL must satisfy: TOML, JSON, or a Burp Suite project options export")]
+    pub scope: Option<PathBuf>,
+
+    #[arg(long = "debug-scope", help = "Report every URL left out of scope and the rule that decided it")]
+    pub debug_scope: bool,
+
+    #[arg(short = 's', long = "crawl-subdomains", help = "Whether to also crawl subdomains of the targets as they are found.")]
     pub crawl_subdomains: bool,
 
+    #[arg(short = 'H', long = "header", value_name = "Name: Value", value_parser = parse_header, help = "A header to send with every request, added to those of the configuration file. Can be repeated")]
+    pub headers: Vec<(String, String)>,
+
+    #[arg(long = "cookies", value_name = "Cookies File", help = "A Netscape cookies file, as exported by browsers and curl, to start the cookie jar with")]
+    pub cookies: Option<PathBuf>,
+
+    #[arg(long = "proxy", value_name = "Proxy URL", help = "Send every request through this proxy: http://, https://, socks5:// or socks5h:// (resolving names through the proxy). Per-host routes go in the configuration file")]
+    pub proxy: Option<String>,
+
+    #[arg(long = "proxy-auth", value_name = "User:Password", value_parser = parse_credentials, help = "The credentials for the proxy")]
+    pub proxy_auth: Option<(String, String)>,
+
+    #[arg(short = 'k', long = "insecure", help = "Crawl hosts whose certificates do not verify, such as self-signed or expired ones")]
+    pub insecure: bool,
+
+    #[arg(long = "ca-bundle", value_name = "PEM File", help = "CA certificates to trust in addition to the built-in roots")]
+    pub ca_bundle: Option<PathBuf>,
+
+    #[arg(long = "client-cert", value_name = "PEM File", help = "A client certificate chain for mutual TLS, optionally followed by its private key")]
+    pub client_cert: Option<PathBuf>,
+
+    #[arg(long = "client-key", value_name = "PEM File", help = "The private key of the client certificate, if not in the same file")]
+    pub client_key: Option<PathBuf>,
+
+    #[arg(short = 'p', long = "probe-ports", value_name = "Ports", value_delimiter = ',', help = "The ports on which HTTPS and HTTP are probed for targets given without a scheme [default: 80,443,8080,8443]")]
+    pub probe_ports: Option<Vec<u16>>,
+
+    #[arg(long = "max-concurrent-requests", value_name = "Requests", help = "The maximum number of requests in flight at once [default: 16]")]
+    pub max_concurrent_requests: Option<usize>,
+
+    #[arg(long = "request-delay", value_name = "Milliseconds", help = "How long to wait before sending each request [default: 0]")]
+    pub request_delay_ms: Option<u64>,
+
+    #[arg(long = "max-body-size", value_name = "Bytes", value_parser = parse_max_body_size, help = "The most bytes of a body to download, the rest being left out and the body flagged as truncated [default: 10485760]")]
+    pub max_body_size: Option<usize>,
+
+    #[arg(long = "connect-timeout", value_name = "Milliseconds", help = "How long to wait for a connection, including the TLS handshake [default: 10000]")]
+    pub connect_timeout_ms: Option<u64>,
+
+    #[arg(long = "read-timeout", value_name = "Milliseconds", help = "How long to wait for the server to send anything once connected [default: 30000]")]
+    pub read_timeout_ms: Option<u64>,
+
+    #[arg(long = "timeout", value_name = "Milliseconds", help = "How long a whole request may take, body included [default: 120000]")]
+    pub timeout_ms: Option<u64>,
+
+    #[arg(long = "retries", value_name = "Retries", help = "How many times to retry a request after a timeout, a dropped connection or a 502, 503 or 504 response [default: 2]")]
+    pub max_retries: Option<u32>,
+
+    #[arg(long = "retry-backoff", value_name = "Milliseconds", help = "How long to wait before the first retry, doubled before each of the next ones [default: 500]")]
+    pub retry_backoff_ms: Option<u64>,
+
     #[arg(short = 'o', long = "output-dir", value_name = "Output File", help = "The database file to use as output")]
-    pub output_file: PathBuf
+    pub output_file: Option<PathBuf>,
+
+    #[arg(long = "max-stored-body-size", value_name = "Bytes", help = "Response bodies larger than this are not stored in the database [default: 10485760]")]
+    pub max_stored_body_size: Option<usize>,
+
+    #[arg(long = "compression-dictionary", help = "Train a zstd dictionary on the first stored bodies and compress the rest with it")]
+    pub compression_dictionary: bool,
+
+    #[arg(long = "jsonl", value_name = "JSON Lines File", help = "Also stream one JSON object per fetched URL to this file, or to stdout if '-'")]
+    pub jsonl_output: Option<PathBuf>,
+
+    #[arg(long = "warc", value_name = "WARC Prefix", help = "Also archive every fetched URL as <prefix>-NNNNN.warc.gz files")]
+    pub warc_output: Option<PathBuf>,
+
+    #[arg(long = "warc-max-size", value_name = "Bytes", help = "Start a new WARC file once the current one reaches this size [default: 1073741824]")]
+    pub warc_max_file_size: Option<u64>,
+
+    #[arg(long = "no-dashboard", help = "Print plain progress logs instead of the live dashboard")]
+    pub no_dashboard: bool,
+
+    #[arg(long = "metrics", value_name = "Address", help = "Serve Prometheus metrics at http://<Address>/metrics while crawling, such as 127.0.0.1:9898")]
+    pub metrics: Option<SocketAddr>
+
+}
+
+/// Parses a header given as `Name: Value`.
+fn parse_header(header: &str) -> Result<(String, String), String> {
+    match header.split_once(':') {
+        Some((name, value)) if !name.trim().is_empty() => Ok((name.trim().to_string(), value.trim().to_string())),
+        _ => Err(format!("expected 'Name: Value', got '{}'", header)),
+    }
+}
+
+/// Parses a maximum body size, which must leave room for some of the body.
+fn parse_max_body_size(size: &str) -> Result<usize, String> {
+    match size.parse() {
+        Ok(0) => Err("must be at least 1 byte".to_string()),
+        Ok(size) => Ok(size),
+        Err(error) => Err(format!("{}", error)),
+    }
+}
+
+/// Parses credentials given as `User:Password`.
+fn parse_credentials(credentials: &str) -> Result<(String, String), String> {
+    match credentials.split_once(':') {
+        Some((user, password)) => Ok((user.to_string(), password.to_string())),
+        None => Err("expected 'User:Password'".to_string()),
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Crawl the targets into an output database
+    Crawl(CrawlArgs),
+
+    /// Continue a crawl session with its settings and targets, following the pages already fetched without fetching them again
+    Resume(ResumeArgs),
+
+    /// Export the stored requests and responses as HAR, JSON Lines or WARC
+    Export(ExportArgs),
+
+    /// Write CSV tables and a Markdown summary per target and per crawl session
+    Report(ReportArgs),
+
+    /// Run a read-only SQL query against an output database
+    Query(QueryArgs),
+
+    /// Compare the URLs and findings of two crawl sessions
+    Diff(DiffArgs),
+
+    /// Show what an output database contains
+    Stats(StatsArgs),
+
+    /// Full-text search over the pages stored in an output database
+    Search(SearchArgs),
+
+    /// Re-run link extraction and the analyzers over stored responses, without network access
+    Reprocess(ReprocessArgs),
+
+    /// Print the effective crawl settings after applying the configuration file, profile and flags
+    PrintConfig(CrawlArgs),
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ResumeArgs {
+
+    #[arg(short = 'd', long = "database", value_name = "Database File", help = "The output database of the crawl to resume")]
+    pub database: PathBuf,
+
+    #[arg(long = "session", value_name = "Session ID", help = "The session to resume, the latest one if omitted")]
+    pub session: Option<i64>,
+
+    #[arg(short = 'c', long = "config", value_name = "Config File", help = "A TOML configuration file applied over the stored settings, to supply the credentials, which are not stored")]
+    pub config: Option<PathBuf>
+
+}
+
+#[derive(clap::Args, Debug)]
+pub struct SearchArgs {
+
+    #[arg(short = 'd', long = "database", value_name = "Database File", help = "The output database to search")]
+    pub database: PathBuf,
+
+    #[arg(short = 'n', long = "limit", default_value_t = 20, help = "The maximum number of results to show")]
+    pub limit: usize,
+
+    #[arg(value_name = "Query", help = "An FTS5 query, e.g. '\"internal use only\"', 'passw*' or 'password NOT reset'")]
+    pub query: String
+
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ExportArgs {
+
+    #[arg(short = 'd', long = "database", value_name = "Database File", help = "The output database to export from")]
+    pub database: PathBuf,
+
+    #[arg(short = 'f', long = "format", value_enum, default_value_t = ExportFormat::Har, help = "The format to export to")]
+    pub format: ExportFormat,
+
+    #[arg(short = 'o', long = "output", value_name = "Output File", help = "The file to write to, stdout if omitted. For WARC, the prefix of the files to write")]
+    pub output: Option<PathBuf>,
+
+    #[arg(short = 't', long = "target", value_name = "Host", help = "Only export exchanges with this host and its subdomains")]
+    pub target: Option<String>,
+
+    #[arg(long = "session", value_name = "Session ID", help = "Only export exchanges from this crawl session")]
+    pub session: Option<i64>
+
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum ExportFormat {
+    /// An HTTP Archive (HAR 1.2)
+    Har,
+    /// One JSON object per response
+    Jsonl,
+    /// Gzipped WARC 1.1 files
+    Warc,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ReportArgs {
+
+    #[arg(short = 'd', long = "database", value_name = "Database File", help = "The output database to report on")]
+    pub database: PathBuf,
+
+    #[arg(short = 'o', long = "output-dir", value_name = "Report Directory", help = "The directory to write the reports to")]
+    pub output_dir: PathBuf,
+
+    #[arg(short = 't', long = "target", value_name = "Host", help = "Only report on this target, instead of every target and session")]
+    pub target: Option<String>,
+
+    #[arg(long = "session", value_name = "Session ID", help = "Only report on this crawl session, instead of every target and session")]
+    pub session: Option<i64>,
+
+    #[arg(long = "html", default_value_t = false, help = "Also render each summary as HTML")]
+    pub html: bool
+
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ReprocessArgs {
+
+    #[arg(short = 'd', long = "database", value_name = "Database File", help = "The output database to reprocess, or to import WARC files into")]
+    pub database: PathBuf,
+
+    #[arg(long = "warc", value_name = "WARC File", num_args = 1.., help = "Process the responses archived in these WARC files instead of those in the database")]
+    pub warc: Vec<PathBuf>,
+
+    #[arg(short = 't', long = "target", value_name = "Host", help = "Only reprocess responses from this host and its subdomains")]
+    pub target: Option<String>,
+
+    #[arg(long = "session", value_name = "Session ID", help = "Only reprocess responses from this crawl session")]
+    pub session: Option<i64>,
+
+    #[arg(long = "max-stored-body-size", value_name = "Bytes", default_value_t = 10 * 1024 * 1024, help = "Imported response bodies larger than this are not stored in the database")]
+    pub max_stored_body_size: usize,
+
+    #[arg(short = 'c', long = "config", value_name = "Config File", help = "A TOML configuration file whose disabled analyzers are not run")]
+    pub config: Option<PathBuf>
+
+}
+
+#[derive(clap::Args, Debug)]
+pub struct QueryArgs {
+
+    #[arg(short = 'd', long = "database", value_name = "Database File", help = "The output database to query")]
+    pub database: PathBuf,
+
+    #[arg(short = 'f', long = "format", value_enum, default_value_t = QueryFormat::Table, help = "How to print the results")]
+    pub format: QueryFormat,
+
+    #[arg(value_name = "SQL", help = "The query, e.g. 'SELECT host, COUNT(*) FROM hosts JOIN urls ON urls.host_id = hosts.id GROUP BY host'")]
+    pub sql: String
+
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum QueryFormat {
+    /// Aligned columns
+    Table,
+    /// Comma-separated values with a header row
+    Csv,
+    /// One JSON object per row
+    Jsonl,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct DiffArgs {
+
+    #[arg(short = 'd', long = "database", value_name = "Database File", help = "The output database holding both sessions")]
+    pub database: PathBuf,
+
+    #[arg(long = "from", value_name = "Session ID", requires = "to", help = "The older session, the second to last one if omitted")]
+    pub from: Option<i64>,
+
+    #[arg(long = "to", value_name = "Session ID", requires = "from", help = "The newer session, the last one if omitted")]
+    pub to: Option<i64>
+
+}
+
+#[derive(clap::Args, Debug)]
+pub struct StatsArgs {
+
+    #[arg(short = 'd', long = "database", value_name = "Database File", help = "The output database to describe")]
+    pub database: PathBuf
 
-}
\ No newline at end of file
+}
@@ -0,0 +1,486 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use reqwest::cookie::Jar;
+use rusqlite::types::Value;
+use tokio::net::TcpListener;
+
+use crate::{
+    crawler::{
+        auth::{HostAuth, Login},
+        crawl_target::{self, CrawlTarget},
+        metrics,
+        proxy::ProxyRoutes,
+        crawler_config::CrawlerConfig,
+        reprocess,
+        scope::Scope,
+        Crawler,
+    },
+    db::{self, blob::BodyReader, sink::DatabaseSink},
+    export::{diff, har, report, stats, stored, ExportFilter},
+    output::{jsonl::JsonLinesSink, warc::WarcSink, OutputRecord, OutputSink},
+    web::{self, host::Host, http::Timeouts, tls::TlsOptions},
+};
+
+use super::{
+    args::{
+        CrawlArgs, DiffArgs, ExportArgs, ExportFormat, QueryArgs, QueryFormat, ReportArgs, ReprocessArgs, ResumeArgs,
+        SearchArgs, StatsArgs,
+    },
+    dashboard::Dashboard,
+    settings::Settings,
+};
+
+/// Print the pages matching a full-text query
+pub fn search(args: &SearchArgs) -> Result<(), Box<dyn Error>> {
+    let db = db::open_read_only(&args.database)?;
+
+    for hit in db::search::search(&db, &args.query, args.limit)? {
+        match hit.title {
+            Some(title) => println!("{} - {}", hit.url, title),
+            None => println!("{}", hit.url),
+        }
+        println!("    {}", hit.snippet);
+    }
+
+    Ok(())
+}
+
+/// Crawl the targets given by the flags, profile and configuration file
+pub async fn crawl(args: &CrawlArgs) -> Result<(), Box<dyn Error>> {
+    let settings = Settings::resolve(args)?;
+    let Some(targets) = &settings.targets else { return Err("No targets given, use --targets or the configuration file".into()); };
+
+    let mut initial_targets: HashSet<CrawlTarget> = HashSet::new();
+    for targets_path in targets {
+        initial_targets.extend(crawl_target::read_targets(targets_path)?);
+    }
+
+    run_crawl(settings, initial_targets, HashMap::new()).await
+}
+
+/// Start a new session continuing an earlier one in the same database
+pub async fn resume(args: &ResumeArgs) -> Result<(), Box<dyn Error>> {
+    let db = db::open_read_only(&args.database)?;
+    let (session_id, settings, targets): (i64, Option<String>, Option<String>) = db.query_row(
+        "SELECT id, settings, targets FROM sessions WHERE ?1 IS NULL OR id = ?1 ORDER BY id DESC LIMIT 1",
+        [args.session],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+    let (Some(settings), Some(targets)) = (settings, targets) else {
+        return Err(format!("Session {} was not started by a crawl that can be resumed", session_id).into());
+    };
+
+    // Sessions started by older versions lack the settings added since, which keep their defaults
+    let supplied = match &args.config {
+        Some(config_path) => Settings::load(config_path)?,
+        None => Settings::default(),
+    };
+    let mut settings = Settings::defaults().merge(toml::from_str(&settings)?).merge(supplied);
+    settings.output.database = Some(args.database.clone());
+
+    // Secrets are not stored with the session, so they have to be given again
+    let redacted = settings.redacted_secrets();
+    if !redacted.is_empty() {
+        return Err(format!(
+            "Session {} used credentials that are not stored with it, supply them with --config: {}",
+            session_id,
+            redacted.join(", ")
+        )
+        .into());
+    }
+
+    let mut initial_targets: HashSet<CrawlTarget> = HashSet::new();
+    for target in targets.lines() {
+        initial_targets.extend(CrawlTarget::parse(target)?);
+    }
+
+    // Every page that got a response, with the links found on it the last time it was fetched
+    let mut fetched_urls: HashMap<String, Vec<String>> = HashMap::new();
+    let mut select = db.prepare(
+        "SELECT urls.url, links.url FROM responses
+        JOIN urls ON urls.id = responses.url_id
+        LEFT JOIN links ON links.response_id = responses.id
+        WHERE responses.id = (SELECT MAX(id) FROM responses AS latest WHERE latest.url_id = responses.url_id)",
+    )?;
+    let mut rows = select.query([])?;
+    while let Some(row) = rows.next()? {
+        let links = fetched_urls.entry(row.get(0)?).or_default();
+        if let Some(link) = row.get(1)? {
+            links.push(link);
+        }
+    }
+    drop(rows);
+    drop(select);
+    db.close().map_err(|(_, error)| error)?;
+
+    tracing::info!(session = session_id, "Resuming session, {} pages already fetched", fetched_urls.len());
+    run_crawl(settings, initial_targets, fetched_urls).await
+}
+
+async fn run_crawl(
+    settings: Settings,
+    initial_targets: HashSet<CrawlTarget>,
+    fetched_urls: HashMap<String, Vec<String>>,
+) -> Result<(), Box<dyn Error>> {
+    let Some(output_file) = &settings.output.database else { return Err("No output database given, use --output-dir or the configuration file".into()); };
+
+    let excluded_hosts: HashSet<Host> = match &settings.exclude {
+        Some(exclude_path) => crawl_target::read_targets(exclude_path)?.iter().map(|target| target.host().clone()).collect(),
+        None => HashSet::new(),
+    };
+
+    let scope = match &settings.scope.file {
+        Some(scope_path) => Scope::load(scope_path)?,
+        None => Scope::default(),
+    }
+    .with_debug(settings.scope.debug.unwrap_or_default());
+
+    let cookies = Arc::new(Jar::default());
+    if let Some(cookies_path) = &settings.cookies {
+        let loaded = web::cookies::load(cookies_path, &cookies)
+            .map_err(|error| format!("Failed to read cookies file {}: {}", cookies_path.display(), error))?;
+        tracing::info!("Loaded {} cookies from {}", loaded, cookies_path.display());
+    }
+    let auth = HostAuth::new(&settings.auth.clone().unwrap_or_default())?;
+    let login = settings.login.as_ref().map(Login::new).transpose()?;
+    let proxies = Arc::new(ProxyRoutes::new(&settings.proxy)?);
+
+    let db_path = path_clean::clean(std::env::current_dir()?.join(output_file));
+    let session_settings = toml::to_string(&settings)?;
+    let metrics_address = settings.metrics;
+
+    // JSON Lines streamed to stdout would be drawn over
+    let show_dashboard = settings.dashboard.unwrap_or_default()
+        && settings.output.jsonl.as_ref().is_none_or(|jsonl| jsonl.as_os_str() != "-");
+
+    // Every setting with a default is set once resolved
+    let crawler_config = CrawlerConfig {
+        initial_targets,
+        excluded_hosts,
+        scope,
+        crawl_subdomains: settings.crawl_subdomains.unwrap_or_default(),
+        probe_ports: settings.probe_ports.unwrap_or_default(),
+        max_concurrent_requests: settings.limits.max_concurrent_requests.unwrap_or(1).max(1),
+        request_delay: Duration::from_millis(settings.limits.request_delay_ms.unwrap_or_default()),
+        max_body_size: settings.limits.max_body_size.unwrap_or_default(),
+        timeouts: Timeouts {
+            connect: Duration::from_millis(settings.timeouts.connect_ms.unwrap_or_default()),
+            read: Duration::from_millis(settings.timeouts.read_ms.unwrap_or_default()),
+            total: Duration::from_millis(settings.timeouts.total_ms.unwrap_or_default()),
+        },
+        max_retries: settings.retries.max_retries.unwrap_or_default(),
+        retry_backoff: Duration::from_millis(settings.retries.backoff_ms.unwrap_or_default()),
+        headers: settings.headers.unwrap_or_default().into_iter().collect(),
+        cookies,
+        auth,
+        login,
+        proxies,
+        tls: TlsOptions {
+            accept_invalid_certs: settings.tls.accept_invalid_certs.unwrap_or_default(),
+            ca_bundle: settings.tls.ca_bundle,
+            client_cert: settings.tls.client_cert,
+            client_key: settings.tls.client_key,
+        },
+        disabled_analyzers: settings.analyzers.disabled.unwrap_or_default().into_iter().collect(),
+        db_path,
+        max_stored_body_size: settings.output.max_stored_body_size.unwrap_or_default(),
+        train_body_dictionary: settings.output.compression_dictionary.unwrap_or_default(),
+        jsonl_output: settings.output.jsonl,
+        warc_output: settings.output.warc,
+        warc_max_file_size: settings.output.warc_max_size.unwrap_or_default(),
+        session_settings,
+        fetched_urls,
+    };
+
+    let mut crawler = Crawler::new(crawler_config)?;
+    let metrics = match metrics_address {
+        Some(address) => {
+            let listener = TcpListener::bind(address).await?;
+            tracing::info!("Serving metrics at http://{}/metrics", listener.local_addr()?);
+            Some(tokio::spawn(metrics::serve(listener, crawler.stats())))
+        }
+        None => None,
+    };
+    let dashboard = if show_dashboard { Dashboard::start(crawler.stats()) } else { None };
+
+    // Ctrl-C stops the crawl once the requests in flight are written out, a second one ends the process at once
+    let stats = crawler.stats();
+    let interrupt = tokio::spawn({
+        let stats = Arc::clone(&stats);
+        async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                tracing::warn!("Interrupted, finishing the requests in flight");
+                stats.stop();
+            }
+            if tokio::signal::ctrl_c().await.is_ok() {
+                std::process::exit(130);
+            }
+        }
+    });
+
+    let crawled = tokio::spawn(async move {
+        crawler.crawl().await;
+    })
+    .await;
+
+    if let Some(dashboard) = dashboard {
+        dashboard.stop();
+    }
+    if let Some(metrics) = metrics {
+        metrics.abort();
+    }
+    interrupt.abort();
+    crawled?;
+
+    if stats.is_stopping() {
+        std::process::exit(130);
+    }
+
+    Ok(())
+}
+
+/// Write the stored exchanges of a target or session as HAR, JSON Lines or WARC
+pub fn export(args: &ExportArgs) -> Result<(), Box<dyn Error>> {
+    let db = db::open_read_only(&args.database)?;
+    let filter = ExportFilter {
+        target: args.target.clone(),
+        session: args.session,
+    };
+
+    if let ExportFormat::Har = args.format {
+        let har = har::build_har(&db, &filter)?;
+
+        let mut output: Box<dyn Write> = match &args.output {
+            Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+            None => Box::new(io::stdout()),
+        };
+        serde_json::to_writer_pretty(&mut output, &har)?;
+        return Ok(output.flush()?);
+    }
+
+    let mut sink: Box<dyn OutputSink> = match args.format {
+        ExportFormat::Warc => {
+            let Some(prefix) = &args.output else { return Err("WARC export needs an output prefix".into()); };
+            Box::new(WarcSink::create(prefix, u64::MAX)?)
+        }
+        _ => Box::new(JsonLinesSink::create(args.output.as_deref().unwrap_or(Path::new("-")))?),
+    };
+
+    stored::for_each_response(&db, &filter, |fetched| sink.write_batch(&[OutputRecord::Response(Box::new(fetched))]))?;
+    sink.finish()
+}
+
+/// Write a report for each target and session, or only for the requested one
+pub fn report(args: &ReportArgs) -> Result<(), Box<dyn Error>> {
+    let db = db::open_read_only(&args.database)?;
+    let mut reports: Vec<(String, String, ExportFilter)> = Vec::new();
+
+    if args.target.is_some() || args.session.is_some() {
+        let filter = ExportFilter { target: args.target.clone(), session: args.session };
+        let name = match (&args.target, args.session) {
+            (Some(target), Some(session)) => format!("{}-session-{}", target, session),
+            (Some(target), None) => target.clone(),
+            (None, _) => format!("session-{}", args.session.unwrap_or_default()),
+        };
+        reports.push((name.clone(), format!("Crawl report: {}", name), filter));
+    } else {
+        for target in report::targets(&db)? {
+            let filter = ExportFilter { target: Some(target.clone()), session: None };
+            reports.push((format!("targets/{}", target), format!("Crawl report: {}", target), filter));
+        }
+        for session in report::sessions(&db)? {
+            let filter = ExportFilter { target: None, session: Some(session) };
+            reports.push((format!("sessions/{}", session), format!("Crawl report: session {}", session), filter));
+        }
+    }
+
+    for (name, title, filter) in reports {
+        let report = report::build_report(&db, &title, &filter)?;
+        let dir = args.output_dir.join(Path::new(&name));
+
+        report::write_csv(&report, &dir)?;
+        fs::write(dir.join("report.md"), report::render_markdown(&report))?;
+        if args.html {
+            fs::write(dir.join("report.html"), report::render_html(&report))?;
+        }
+
+        println!("Wrote report to {}", dir.display());
+    }
+
+    Ok(())
+}
+
+/// Re-run the crawl pipeline over stored responses or archived WARC files
+pub fn reprocess(args: &ReprocessArgs) -> Result<(), Box<dyn Error>> {
+    let settings = match &args.config {
+        Some(config_path) => Settings::load(config_path)?,
+        None => Settings::default(),
+    };
+    let disabled_analyzers: HashSet<String> = settings.analyzers.disabled.unwrap_or_default().into_iter().collect();
+
+    if args.warc.is_empty() {
+        let mut db = db::open(&args.database)?;
+        let filter = ExportFilter { target: args.target.clone(), session: args.session };
+        let reprocessed = reprocess::reprocess_database(&mut db, &filter, &disabled_analyzers)?;
+
+        println!("Reprocessed {} responses", reprocessed);
+    } else {
+        let sink = DatabaseSink::open(&args.database, args.max_stored_body_size, false)?;
+        let imported = reprocess::reprocess_warc(&args.warc, Box::new(sink), &disabled_analyzers)?;
+
+        println!("Imported {} responses", imported);
+    }
+
+    Ok(())
+}
+
+/// Print the effective crawl settings as TOML
+pub fn print_config(args: &CrawlArgs) -> Result<(), Box<dyn Error>> {
+    let settings = Settings::resolve(args)?;
+    print!("{}", toml::to_string_pretty(&settings)?);
+
+    Ok(())
+}
+
+/// Print the results of a read-only SQL query
+pub fn query(args: &QueryArgs) -> Result<(), Box<dyn Error>> {
+    let db = db::open_read_only(&args.database)?;
+    db.pragma_update(None, "query_only", true)?;
+
+    let mut statement = db.prepare(&args.sql)?;
+    let columns: Vec<String> = statement.column_names().into_iter().map(String::from).collect();
+
+    let mut bodies = BodyReader::default();
+    let mut rows: Vec<Vec<Value>> = Vec::new();
+    let mut result = statement.query([])?;
+    while let Some(row) = result.next()? {
+        let mut values: Vec<Value> = (0..columns.len()).map(|index| row.get(index)).collect::<Result<_, _>>()?;
+        // Stored bodies are shown decompressed
+        for value in &mut values {
+            if let Value::Blob(blob) = value {
+                if let Some(body) = bodies.decompress(&db, blob)? {
+                    *value = Value::Text(String::from_utf8_lossy(&body).into_owned());
+                }
+            }
+        }
+        rows.push(values);
+    }
+
+    let text = |value: &Value| match value {
+        Value::Null => String::new(),
+        Value::Integer(integer) => integer.to_string(),
+        Value::Real(real) => real.to_string(),
+        Value::Text(text) => text.clone(),
+        Value::Blob(blob) => format!("<{} bytes>", blob.len()),
+    };
+
+    match args.format {
+        QueryFormat::Table => {
+            let mut widths: Vec<usize> = columns.iter().map(|column| column.chars().count()).collect();
+            for row in &rows {
+                for (width, value) in widths.iter_mut().zip(row) {
+                    *width = (*width).max(text(value).chars().count());
+                }
+            }
+
+            let line = |values: Vec<String>| {
+                let cells: Vec<String> = values.iter().zip(&widths).map(|(value, &width)| format!("{:width$}", value, width = width)).collect();
+                println!("{}", cells.join("  ").trim_end());
+            };
+            line(columns.clone());
+            line(widths.iter().map(|&width| "-".repeat(width)).collect());
+            for row in &rows {
+                line(row.iter().map(text).collect());
+            }
+        }
+
+        QueryFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(io::stdout());
+            writer.write_record(&columns)?;
+            for row in &rows {
+                writer.write_record(row.iter().map(text))?;
+            }
+            writer.flush()?;
+        }
+
+        QueryFormat::Jsonl => {
+            for row in &rows {
+                let object: serde_json::Map<String, serde_json::Value> = columns
+                    .iter()
+                    .zip(row)
+                    .map(|(column, value)| {
+                        let value = match value {
+                            Value::Null => serde_json::Value::Null,
+                            Value::Integer(integer) => (*integer).into(),
+                            Value::Real(real) => (*real).into(),
+                            value => text(value).into(),
+                        };
+                        (column.clone(), value)
+                    })
+                    .collect();
+                println!("{}", serde_json::Value::Object(object));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Print what changed between two crawl sessions
+pub fn diff(args: &DiffArgs) -> Result<(), Box<dyn Error>> {
+    let db = db::open_read_only(&args.database)?;
+    let (from, to) = match (args.from, args.to) {
+        (Some(from), Some(to)) => (from, to),
+        _ => diff::last_two_sessions(&db)?.ok_or("The database holds fewer than two sessions with responses, choose them with --from and --to")?,
+    };
+
+    let diff = diff::diff_sessions(&db, from, to)?;
+    println!("Session {} -> {}", from, to);
+    for (url, status) in &diff.added {
+        println!("+ {} {}", status, url);
+    }
+    for (url, status) in &diff.removed {
+        println!("- {} {}", status, url);
+    }
+    for (url, old_status, new_status) in &diff.changed {
+        println!("~ {} -> {} {}", old_status, new_status, url);
+    }
+    for (url, kind, detail) in &diff.new_findings {
+        println!("+ finding {} {}: {}", kind, url, detail);
+    }
+    for (url, kind, detail) in &diff.resolved_findings {
+        println!("- finding {} {}: {}", kind, url, detail);
+    }
+
+    Ok(())
+}
+
+/// Print the statistics of an output database
+pub fn stats(args: &StatsArgs) -> Result<(), Box<dyn Error>> {
+    let db = db::open_read_only(&args.database)?;
+    let stats = stats::collect(&db)?;
+
+    println!("Sessions:       {}", stats.sessions);
+    println!("Hosts:          {}", stats.hosts);
+    println!("Live services:  {}", stats.live_services);
+    println!("URLs:           {}", stats.urls);
+    println!("Responses:      {}", stats.responses);
+    println!("Stored bodies:  {} ({} bytes, {} compressed)", stats.stored_bodies, stats.body_bytes, stats.stored_bytes);
+
+    println!("Status codes:");
+    for (class, count) in &stats.status_classes {
+        println!("    {}: {}", class, count);
+    }
+    println!("Findings:");
+    for (kind, count) in &stats.findings {
+        println!("    {}: {}", kind, count);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,162 @@
+use std::io::{self, IsTerminal};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+
+use super::{logging, Cli};
+use crate::crawler::stats::CrawlStats;
+
+/// How often the dashboard is redrawn
+const REFRESH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// A full-screen view of a running crawl, with keys to pause it and to skip targets
+pub struct Dashboard {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl Dashboard {
+    /// Shows the dashboard on its own thread, unless stdout is not a terminal.
+    pub fn start(stats: Arc<CrawlStats>) -> Option<Dashboard> {
+        if !io::stdout().is_terminal() {
+            return None;
+        }
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || {
+                if let Err(error) = run(&stats, &stop) {
+                    logging::release();
+                    tracing::error!("Dashboard failed: {}", error);
+                }
+            })
+        };
+
+        Some(Dashboard { stop, thread })
+    }
+
+    /// Gives the terminal back, printing the log lines that were kept while the dashboard was shown.
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        let _ = self.thread.join();
+    }
+}
+
+fn run(stats: &CrawlStats, stop: &AtomicBool) -> io::Result<()> {
+    let mut cli = Cli::init()?;
+    logging::hold();
+
+    let mut selected = 0;
+    let mut rate = 0.0;
+    let mut last_sample = (Instant::now(), 0);
+
+    while !stop.load(Ordering::Relaxed) {
+        let (sampled_at, sampled_requests) = last_sample;
+        if sampled_at.elapsed() >= Duration::from_secs(1) {
+            let requests = stats.requests.load(Ordering::Relaxed);
+            rate = (requests - sampled_requests) as f64 / sampled_at.elapsed().as_secs_f64();
+            last_sample = (Instant::now(), requests);
+        }
+
+        let targets = stats.active_targets();
+        selected = selected.min(targets.len().saturating_sub(1));
+        cli.draw(&render(stats, selected, rate))?;
+
+        if !event::poll(REFRESH_INTERVAL)? {
+            continue;
+        }
+        let Event::Key(key) = event::read()? else { continue; };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+
+        match key.code {
+            KeyCode::Char('p') => stats.set_paused(!stats.is_paused()),
+            KeyCode::Char('s') => {
+                if let Some((origin, _)) = targets.get(selected) {
+                    stats.skip(origin);
+                    tracing::info!(origin = %origin, "Skipping target");
+                }
+            }
+            KeyCode::Up => selected = selected.saturating_sub(1),
+            KeyCode::Down => selected += 1,
+
+            // Raw mode swallows the interrupt signal. The terminal is given back while the crawl winds down.
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                tracing::warn!("Interrupted, finishing the requests in flight");
+                stats.stop();
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    drop(cli);
+    for line in logging::release() {
+        eprintln!("{}", line);
+    }
+
+    Ok(())
+}
+
+fn render(stats: &CrawlStats, selected: usize, rate: f64) -> Vec<String> {
+    let elapsed = stats.started.elapsed().as_secs();
+    let state = if stats.is_paused() { "PAUSED" } else { "RUNNING" };
+    let mut lines = vec![
+        format!(
+            "{} {}  {}  {:02}:{:02}:{:02}",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION"),
+            state,
+            elapsed / 3600,
+            elapsed / 60 % 60,
+            elapsed % 60
+        ),
+        format!(
+            "Requests: {} total, {:.1}/s, {} in flight, {} errors",
+            stats.requests.load(Ordering::Relaxed),
+            rate,
+            stats.in_flight.load(Ordering::Relaxed),
+            stats.errors.load(Ordering::Relaxed)
+        ),
+    ];
+
+    let statuses: Vec<String> = stats.statuses().iter().map(|(status, count)| format!("{}: {}", status, count)).collect();
+    lines.push(format!("Status codes: {}", statuses.join("  ")));
+    lines.push(String::new());
+
+    let targets = stats.active_targets();
+    lines.push(format!("{:<60} {:>8} {:>10} {:>8}", format!("Active targets ({})", targets.len()), "queued", "in flight", "done"));
+    for (index, (origin, target)) in targets.iter().enumerate() {
+        let marker = if index == selected { ">" } else { " " };
+        let skipped = if stats.is_skipped(origin) { " (skipping)" } else { "" };
+        lines.push(format!(
+            "{} {:<58} {:>8} {:>10} {:>8}",
+            marker,
+            format!("{}{}", origin, skipped),
+            target.queued.load(Ordering::Relaxed),
+            target.in_flight.load(Ordering::Relaxed),
+            target.done.load(Ordering::Relaxed)
+        ));
+    }
+
+    lines.push(String::new());
+    lines.push(format!("Recently discovered: {}", stats.recent_hosts().join("  ")));
+
+    lines.push(String::new());
+    lines.push("Recent errors:".to_string());
+    lines.extend(stats.recent_errors().iter().map(|error| format!("  {}", error)));
+
+    lines.push(String::new());
+    lines.push("Log:".to_string());
+    lines.extend(logging::recent().iter().map(|line| format!("  {}", line)));
+
+    lines.push(String::new());
+    lines.push("p pause/resume  s skip selected target  Up/Down select  Ctrl-C quit".to_string());
+
+    lines
+}
@@ -0,0 +1,103 @@
+use std::collections::VecDeque;
+use std::env;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, IsTerminal, Write};
+use std::sync::{Arc, Mutex};
+
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
+
+use super::args::{LogArgs, LogLevel};
+
+/// How many log lines are kept while the dashboard owns the terminal
+const HELD_CAPACITY: usize = 10;
+
+/// The most recent log lines while the dashboard owns the terminal, or `None` while they go to stderr
+static HELD: Mutex<Option<VecDeque<String>>> = Mutex::new(None);
+
+/// Logs to stderr and, if asked, to a JSON Lines file.
+pub fn init(args: &LogArgs) -> Result<(), Box<dyn Error>> {
+    let stderr = fmt::layer()
+        .with_writer(|| Stderr)
+        .with_ansi(false)
+        .with_target(false)
+        .with_filter(filter(args)?);
+
+    let file = match &args.file {
+        Some(path) => Some(fmt::layer().json().with_writer(Arc::new(File::create(path)?)).with_filter(filter(args)?)),
+        None => None,
+    };
+
+    let registry = tracing_subscriber::registry().with(stderr).with(file);
+
+    #[cfg(feature = "console")]
+    let registry = registry.with(console_subscriber::spawn());
+
+    registry.try_init()?;
+    Ok(())
+}
+
+/// The level applies to everything not named by the filter directives.
+fn filter(args: &LogArgs) -> Result<EnvFilter, Box<dyn Error>> {
+    let level = LevelFilter::from(args.level);
+    let directives = match args.filter.clone().or_else(|| env::var(EnvFilter::DEFAULT_ENV).ok()) {
+        Some(directives) if !directives.trim().is_empty() => format!("{},{}", level, directives),
+        _ => level.to_string(),
+    };
+
+    Ok(EnvFilter::builder().parse(directives)?)
+}
+
+impl From<LogLevel> for LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Error => LevelFilter::ERROR,
+            LogLevel::Warn => LevelFilter::WARN,
+            LogLevel::Info => LevelFilter::INFO,
+            LogLevel::Debug => LevelFilter::DEBUG,
+            LogLevel::Trace => LevelFilter::TRACE,
+        }
+    }
+}
+
+/// Keeps the log lines instead of printing them, until released. Only lines bound for the terminal are kept, when
+/// stderr is redirected they are written through.
+pub fn hold() {
+    if !io::stderr().is_terminal() {
+        return;
+    }
+
+    HELD.lock().unwrap().get_or_insert_with(VecDeque::new);
+}
+
+/// Prints to stderr again, returning the most recent lines kept while held.
+pub fn release() -> Vec<String> {
+    HELD.lock().unwrap().take().map(Vec::from).unwrap_or_default()
+}
+
+/// The most recent lines kept while held.
+pub fn recent() -> Vec<String> {
+    HELD.lock().unwrap().iter().flatten().cloned().collect()
+}
+
+/// Writes log lines to stderr, or keeps them while held
+struct Stderr;
+
+impl Write for Stderr {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(held) = HELD.lock().unwrap().as_mut() {
+            if held.len() == HELD_CAPACITY {
+                held.pop_front();
+            }
+            held.push_back(String::from_utf8_lossy(buf).trim_end().to_string());
+            return Ok(buf.len());
+        }
+
+        io::stderr().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
@@ -1,20 +1,55 @@
-use std::io::stdout;
+use std::io::{self, stdout, Write};
+
+use crossterm::{
+    cursor, execute, queue,
+    terminal::{self, ClearType},
+};
 
 pub mod args;
+pub mod commands;
+pub mod dashboard;
+pub mod logging;
+pub mod settings;
 
+/// The terminal, taken over for full-screen output until dropped
 #[derive(Debug)]
 pub struct Cli {
     stdout: std::io::Stdout
 }
 
 impl Cli {
-    pub fn init() -> Self {
-        Cli {
-            stdout: stdout()
+    pub fn init() -> io::Result<Self> {
+        let mut stdout = stdout();
+        terminal::enable_raw_mode()?;
+        execute!(stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+
+        Ok(Cli {
+            stdout
+        })
+    }
+
+    pub fn clear(&mut self) -> io::Result<()> {
+        queue!(self.stdout, terminal::Clear(ClearType::All), cursor::MoveTo(0, 0))
+    }
+
+    /// Replaces the screen with the given lines, cut to the size of the terminal.
+    pub fn draw(&mut self, lines: &[String]) -> io::Result<()> {
+        let (width, height) = terminal::size()?;
+        self.clear()?;
+
+        for (row, line) in lines.iter().take(height as usize).enumerate() {
+            let line: String = line.chars().take(width as usize).collect();
+            queue!(self.stdout, cursor::MoveTo(0, row as u16))?;
+            self.stdout.write_all(line.as_bytes())?;
         }
+
+        self.stdout.flush()
     }
+}
 
-    pub fn clear(&self) {
-        
+impl Drop for Cli {
+    fn drop(&mut self) {
+        let _ = execute!(self.stdout, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
     }
 }
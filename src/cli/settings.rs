@@ -0,0 +1,448 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+use super::args::CrawlArgs;
+use crate::{
+    util::redact::{self, REDACTED},
+    web::auth::Credentials,
+};
+
+/// Crawl settings, as read from a configuration file or profile. Unset settings are inherited from the layer below.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    /// The profile applied on top of the configuration file, unless another is chosen on the command line
+    pub profile: Option<String>,
+    pub targets: Option<Vec<PathBuf>>,
+    pub exclude: Option<PathBuf>,
+    pub crawl_subdomains: Option<bool>,
+    pub probe_ports: Option<Vec<u16>>,
+    pub limits: LimitSettings,
+    pub timeouts: TimeoutSettings,
+    pub retries: RetrySettings,
+    pub scope: ScopeSettings,
+    /// Sent with every request. The values of credentials such as Authorization and Cookie are redacted when serialized
+    #[serde(serialize_with = "redact::header_values")]
+    pub headers: Option<BTreeMap<String, String>>,
+    /// A Netscape cookies file to seed the cookie jar with
+    pub cookies: Option<PathBuf>,
+    /// Basic or bearer credentials, by host glob
+    pub auth: Option<IndexMap<String, Credentials>>,
+    pub login: Option<LoginSettings>,
+    pub proxy: ProxySettings,
+    pub tls: TlsSettings,
+    pub output: OutputSettings,
+    pub analyzers: AnalyzerSettings,
+    /// Whether to show the live dashboard when stdout is a terminal
+    pub dashboard: Option<bool>,
+    /// Where to serve Prometheus metrics while crawling
+    pub metrics: Option<SocketAddr>,
+    /// Profiles defined by the configuration file, added to or replacing the built-in ones
+    #[serde(skip_serializing)]
+    pub profiles: BTreeMap<String, Settings>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitSettings {
+    pub max_concurrent_requests: Option<usize>,
+    pub request_delay_ms: Option<u64>,
+    /// Bodies are downloaded up to this many bytes, and truncated beyond. 0 downloads no bodies at all
+    pub max_body_size: Option<usize>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimeoutSettings {
+    /// Until the connection is established, including the TLS handshake
+    pub connect_ms: Option<u64>,
+    /// Between receiving anything from the server
+    pub read_ms: Option<u64>,
+    /// For the whole exchange, including the body
+    pub total_ms: Option<u64>,
+}
+
+/// How timeouts, dropped connections and 502, 503 and 504 responses are retried
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetrySettings {
+    pub max_retries: Option<u32>,
+    /// The wait before the first retry, doubled before each of the next ones
+    pub backoff_ms: Option<u64>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScopeSettings {
+    pub file: Option<PathBuf>,
+    pub debug: Option<bool>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputSettings {
+    pub database: Option<PathBuf>,
+    pub max_stored_body_size: Option<usize>,
+    pub compression_dictionary: Option<bool>,
+    pub jsonl: Option<PathBuf>,
+    pub warc: Option<PathBuf>,
+    pub warc_max_size: Option<u64>,
+}
+
+/// How to log in to the crawled application, as configured
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoginSettings {
+    /// Where the login form is posted
+    pub url: String,
+    /// The page with the login form, fetched first for its cookies and hidden fields such as CSRF tokens
+    pub form_page: Option<String>,
+    /// The form fields to post, such as the username and password
+    #[serde(serialize_with = "redact::secret_values")]
+    pub fields: BTreeMap<String, String>,
+    /// A regex matching the body of pages shown once logged out, such as the login form
+    pub logged_out: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsSettings {
+    /// Connect even when certificates do not verify, such as when they are self-signed or expired
+    pub accept_invalid_certs: Option<bool>,
+    /// PEM certificates to trust in addition to the built-in roots
+    pub ca_bundle: Option<PathBuf>,
+    /// A PEM certificate chain for servers that ask for one, optionally with its private key
+    pub client_cert: Option<PathBuf>,
+    /// The PEM private key of the client certificate, if not in the same file
+    pub client_key: Option<PathBuf>,
+}
+
+/// The proxy every request goes through, unless a route picks another for its host
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProxySettings {
+    /// An http, https, socks5 or socks5h URL, or `direct`
+    pub url: Option<String>,
+    pub username: Option<String>,
+    #[serde(serialize_with = "redact::optional_secret")]
+    pub password: Option<String>,
+    pub routes: Option<Vec<ProxyRoute>>,
+}
+
+/// A proxy for the hosts matching a glob and/or the IP addresses in a CIDR range
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProxyRoute {
+    pub hosts: Option<String>,
+    pub cidr: Option<String>,
+    /// An http, https, socks5 or socks5h URL, or `direct`
+    pub url: String,
+    pub username: Option<String>,
+    #[serde(serialize_with = "redact::optional_secret")]
+    pub password: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnalyzerSettings {
+    /// The kinds of findings not to report
+    pub disabled: Option<Vec<String>>,
+}
+
+impl Settings {
+    /// The settings used when nothing else is configured.
+    pub fn defaults() -> Settings {
+        Settings {
+            crawl_subdomains: Some(false),
+            probe_ports: Some(vec![80, 443, 8080, 8443]),
+            limits: LimitSettings {
+                max_concurrent_requests: Some(16),
+                request_delay_ms: Some(0),
+                max_body_size: Some(10 * 1024 * 1024),
+            },
+            timeouts: TimeoutSettings {
+                connect_ms: Some(10_000),
+                read_ms: Some(30_000),
+                total_ms: Some(120_000),
+            },
+            retries: RetrySettings {
+                max_retries: Some(2),
+                backoff_ms: Some(500),
+            },
+            scope: ScopeSettings {
+                file: None,
+                debug: Some(false),
+            },
+            headers: Some(BTreeMap::new()),
+            output: OutputSettings {
+                database: None,
+                max_stored_body_size: Some(10 * 1024 * 1024),
+                compression_dictionary: Some(false),
+                jsonl: None,
+                warc: None,
+                warc_max_size: Some(1024 * 1024 * 1024),
+            },
+            analyzers: AnalyzerSettings { disabled: Some(Vec::new()) },
+            dashboard: Some(true),
+            tls: TlsSettings {
+                accept_invalid_certs: Some(false),
+                ..TlsSettings::default()
+            },
+            ..Settings::default()
+        }
+    }
+
+    /// The built-in profiles.
+    fn builtin_profile(name: &str) -> Option<Settings> {
+        match name {
+            // Few, slow requests to the services most likely to exist
+            "stealthy" => Some(Settings {
+                crawl_subdomains: Some(false),
+                probe_ports: Some(vec![443, 80]),
+                limits: LimitSettings {
+                    max_concurrent_requests: Some(2),
+                    request_delay_ms: Some(1000),
+                    max_body_size: None,
+                },
+                ..Settings::default()
+            }),
+
+            // As many requests as possible, to as many services as possible
+            "aggressive" => Some(Settings {
+                crawl_subdomains: Some(true),
+                probe_ports: Some(vec![80, 443, 8000, 8008, 8080, 8443, 8888, 9443]),
+                limits: LimitSettings {
+                    max_concurrent_requests: Some(64),
+                    request_delay_ms: Some(0),
+                    max_body_size: None,
+                },
+                ..Settings::default()
+            }),
+
+            _ => None,
+        }
+    }
+
+    /// Reads a TOML configuration file.
+    pub fn load(path: &Path) -> Result<Settings, SettingsError> {
+        let contents = fs::read_to_string(path).map_err(|error| SettingsError::new(path, &error.to_string()))?;
+        toml::from_str(&contents).map_err(|error| SettingsError::new(path, &error.to_string()))
+    }
+
+    /// Resolves the effective settings of a crawl: defaults < configuration file < profile < command line flags.
+    pub fn resolve(args: &CrawlArgs) -> Result<Settings, SettingsError> {
+        let file = match &args.config {
+            Some(config_path) => Self::load(config_path)?,
+            None => Settings::default(),
+        };
+
+        let profile = match args.profile.as_ref().or(file.profile.as_ref()) {
+            Some(name) => match file.profiles.get(name).cloned().or_else(|| Self::builtin_profile(name)) {
+                Some(profile) => profile.with_name(name),
+                None => return Err(SettingsError::unknown_profile(name)),
+            },
+            None => Settings::default(),
+        };
+
+        Ok(Self::defaults().merge(file).merge(profile).merge(Self::from_args(args)))
+    }
+
+    /// Returns the settings given as command line flags.
+    fn from_args(args: &CrawlArgs) -> Settings {
+        Settings {
+            profile: args.profile.clone(),
+            targets: (!args.targets.is_empty()).then(|| args.targets.clone()),
+            exclude: args.exclude.clone(),
+            crawl_subdomains: args.crawl_subdomains.then_some(true),
+            probe_ports: args.probe_ports.clone(),
+            limits: LimitSettings {
+                max_concurrent_requests: args.max_concurrent_requests,
+                request_delay_ms: args.request_delay_ms,
+                max_body_size: args.max_body_size,
+            },
+            timeouts: TimeoutSettings {
+                connect_ms: args.connect_timeout_ms,
+                read_ms: args.read_timeout_ms,
+                total_ms: args.timeout_ms,
+            },
+            retries: RetrySettings {
+                max_retries: args.max_retries,
+                backoff_ms: args.retry_backoff_ms,
+            },
+            scope: ScopeSettings {
+                file: args.scope.clone(),
+                debug: args.debug_scope.then_some(true),
+            },
+            headers: (!args.headers.is_empty()).then(|| args.headers.iter().cloned().collect()),
+            cookies: args.cookies.clone(),
+            auth: None,
+            login: None,
+            proxy: ProxySettings {
+                url: args.proxy.clone(),
+                username: args.proxy_auth.as_ref().map(|(username, _)| username.clone()),
+                password: args.proxy_auth.as_ref().map(|(_, password)| password.clone()),
+                routes: None,
+            },
+            tls: TlsSettings {
+                accept_invalid_certs: args.insecure.then_some(true),
+                ca_bundle: args.ca_bundle.clone(),
+                client_cert: args.client_cert.clone(),
+                client_key: args.client_key.clone(),
+            },
+            output: OutputSettings {
+                database: args.output_file.clone(),
+                max_stored_body_size: args.max_stored_body_size,
+                compression_dictionary: args.compression_dictionary.then_some(true),
+                jsonl: args.jsonl_output.clone(),
+                warc: args.warc_output.clone(),
+                warc_max_size: args.warc_max_file_size,
+            },
+            analyzers: AnalyzerSettings::default(),
+            dashboard: args.no_dashboard.then_some(false),
+            metrics: args.metrics,
+            profiles: BTreeMap::new(),
+        }
+    }
+
+    /// The secrets that were redacted when these settings were stored, and have to be supplied again.
+    pub fn redacted_secrets(&self) -> Vec<String> {
+        let mut redacted = Vec::new();
+        for (name, value) in self.headers.iter().flatten() {
+            if value == REDACTED {
+                redacted.push(format!("headers.\"{}\"", name));
+            }
+        }
+        for (hosts, credentials) in self.auth.iter().flatten() {
+            let secret = match credentials {
+                Credentials::Basic { password, .. } => password.as_deref(),
+                Credentials::Bearer { token } => Some(token.as_str()),
+            };
+            if secret == Some(REDACTED) {
+                redacted.push(format!("auth.\"{}\"", hosts));
+            }
+        }
+        if self.login.as_ref().is_some_and(|login| login.fields.values().any(|value| value == REDACTED)) {
+            redacted.push("login.fields".to_string());
+        }
+        if self.proxy.password.as_deref() == Some(REDACTED) {
+            redacted.push("proxy.password".to_string());
+        }
+        for (index, route) in self.proxy.routes.iter().flatten().enumerate() {
+            if route.password.as_deref() == Some(REDACTED) {
+                redacted.push(format!("proxy.routes[{}].password", index));
+            }
+        }
+        redacted
+    }
+
+    fn with_name(mut self, name: &str) -> Settings {
+        self.profile = Some(name.to_string());
+        self
+    }
+
+    /// Layers other settings on top of these, the other settings winning wherever they are set.
+    pub fn merge(self, other: Settings) -> Settings {
+        Settings {
+            profile: other.profile.or(self.profile),
+            targets: other.targets.or(self.targets),
+            exclude: other.exclude.or(self.exclude),
+            crawl_subdomains: other.crawl_subdomains.or(self.crawl_subdomains),
+            probe_ports: other.probe_ports.or(self.probe_ports),
+            limits: LimitSettings {
+                max_concurrent_requests: other.limits.max_concurrent_requests.or(self.limits.max_concurrent_requests),
+                request_delay_ms: other.limits.request_delay_ms.or(self.limits.request_delay_ms),
+                max_body_size: other.limits.max_body_size.or(self.limits.max_body_size),
+            },
+            timeouts: TimeoutSettings {
+                connect_ms: other.timeouts.connect_ms.or(self.timeouts.connect_ms),
+                read_ms: other.timeouts.read_ms.or(self.timeouts.read_ms),
+                total_ms: other.timeouts.total_ms.or(self.timeouts.total_ms),
+            },
+            retries: RetrySettings {
+                max_retries: other.retries.max_retries.or(self.retries.max_retries),
+                backoff_ms: other.retries.backoff_ms.or(self.retries.backoff_ms),
+            },
+            scope: ScopeSettings {
+                file: other.scope.file.or(self.scope.file),
+                debug: other.scope.debug.or(self.scope.debug),
+            },
+            // Headers add up, so a profile can add one without repeating the rest
+            headers: match (self.headers, other.headers) {
+                (Some(mut headers), Some(other_headers)) => {
+                    headers.extend(other_headers);
+                    Some(headers)
+                }
+                (headers, other_headers) => other_headers.or(headers),
+            },
+            cookies: other.cookies.or(self.cookies),
+            // Like headers, credentials add up
+            auth: match (self.auth, other.auth) {
+                (Some(mut auth), Some(other_auth)) => {
+                    auth.extend(other_auth);
+                    Some(auth)
+                }
+                (auth, other_auth) => other_auth.or(auth),
+            },
+            login: other.login.or(self.login),
+            proxy: ProxySettings {
+                url: other.proxy.url.or(self.proxy.url),
+                username: other.proxy.username.or(self.proxy.username),
+                password: other.proxy.password.or(self.proxy.password),
+                routes: other.proxy.routes.or(self.proxy.routes),
+            },
+            tls: TlsSettings {
+                accept_invalid_certs: other.tls.accept_invalid_certs.or(self.tls.accept_invalid_certs),
+                ca_bundle: other.tls.ca_bundle.or(self.tls.ca_bundle),
+                client_cert: other.tls.client_cert.or(self.tls.client_cert),
+                client_key: other.tls.client_key.or(self.tls.client_key),
+            },
+            output: OutputSettings {
+                database: other.output.database.or(self.output.database),
+                max_stored_body_size: other.output.max_stored_body_size.or(self.output.max_stored_body_size),
+                compression_dictionary: other.output.compression_dictionary.or(self.output.compression_dictionary),
+                jsonl: other.output.jsonl.or(self.output.jsonl),
+                warc: other.output.warc.or(self.output.warc),
+                warc_max_size: other.output.warc_max_size.or(self.output.warc_max_size),
+            },
+            analyzers: AnalyzerSettings {
+                disabled: other.analyzers.disabled.or(self.analyzers.disabled),
+            },
+            dashboard: other.dashboard.or(self.dashboard),
+            metrics: other.metrics.or(self.metrics),
+            profiles: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SettingsError {
+    message: String,
+}
+
+impl SettingsError {
+    fn new(path: &Path, reason: &str) -> SettingsError {
+        SettingsError {
+            message: format!("Invalid configuration file {}: {}", path.display(), reason),
+        }
+    }
+
+    fn unknown_profile(name: &str) -> SettingsError {
+        SettingsError {
+            message: format!("Unknown profile {}", name),
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
@@ -0,0 +1,205 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex as StdMutex;
+
+use globset::{Glob, GlobMatcher};
+use indexmap::IndexMap;
+use regex::Regex;
+use reqwest::{header, Client, StatusCode};
+use scraper::{Html, Selector};
+use tokio::sync::Mutex;
+use url::Url;
+
+use crate::{cli::settings::LoginSettings, web::auth::Credentials};
+
+/// The credentials of each host, matched by glob
+#[derive(Debug, Default)]
+pub struct HostAuth {
+    hosts: Vec<(GlobMatcher, Credentials)>,
+}
+
+impl HostAuth {
+    pub fn new(hosts: &IndexMap<String, Credentials>) -> Result<HostAuth, AuthError> {
+        let hosts = hosts
+            .iter()
+            .map(|(pattern, credentials)| {
+                let glob = Glob::new(&pattern.to_ascii_lowercase())
+                    .map_err(|error| AuthError::new(&format!("Invalid auth host {}: {}", pattern, error)))?;
+                Ok((glob.compile_matcher(), credentials.clone()))
+            })
+            .collect::<Result<_, AuthError>>()?;
+
+        Ok(HostAuth { hosts })
+    }
+
+    /// The credentials for the host of a URL, if any. The first matching host pattern in the file wins.
+    pub fn credentials(&self, url: &Url) -> Option<&Credentials> {
+        let host = url.host_str()?.trim_matches(['[', ']']).to_ascii_lowercase();
+        self.hosts.iter().find(|(glob, _)| glob.is_match(&host)).map(|(_, credentials)| credentials)
+    }
+}
+
+/// How many logins in a row may leave the page that asked for them logged out before no more are attempted
+const MAX_FUTILE_LOGINS: u32 = 3;
+
+/// A scripted login, repeated whenever the session is lost. The session cookie is kept by the client's cookie jar.
+#[derive(Debug)]
+pub struct Login {
+    url: Url,
+    form_page: Option<Url>,
+    fields: BTreeMap<String, String>,
+    logged_out: Option<Regex>,
+    /// Bumped by every login, so that tasks which saw the same lost session log in only once
+    generation: AtomicU64,
+    logging_in: Mutex<()>,
+    futile_logins: StdMutex<FutileLogins>,
+}
+
+/// The logins in a row that did not restore the session, counted once per generation
+#[derive(Debug, Default)]
+struct FutileLogins {
+    generation: u64,
+    count: u32,
+}
+
+impl Login {
+    pub fn new(settings: &LoginSettings) -> Result<Login, AuthError> {
+        let parse = |url: &str| Url::parse(url).map_err(|error| AuthError::new(&format!("Invalid login URL {}: {}", url, error)));
+
+        Ok(Login {
+            url: parse(&settings.url)?,
+            form_page: settings.form_page.as_deref().map(parse).transpose()?,
+            fields: settings.fields.clone(),
+            logged_out: match &settings.logged_out {
+                Some(logged_out) => Some(
+                    Regex::new(logged_out).map_err(|error| AuthError::new(&format!("Invalid logged out pattern: {}", error)))?,
+                ),
+                None => None,
+            },
+            generation: AtomicU64::new(0),
+            logging_in: Mutex::new(()),
+            futile_logins: StdMutex::new(FutileLogins::default()),
+        })
+    }
+
+    /// Identifies the current session, to pass to [`Login::login`] once it is found to be lost.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    /// Whether logging in again is still worth it, i.e. recent logins did restore the session.
+    pub fn may_login(&self) -> bool {
+        self.futile_logins.lock().unwrap().count < MAX_FUTILE_LOGINS
+    }
+
+    /// Records whether the page fetched again after a login was no longer logged out. Pages that stay logged out,
+    /// such as those the account may not see, would otherwise have every response trigger another login.
+    pub fn record_login_result(&self, restored: bool) {
+        let generation = self.generation();
+        let mut futile_logins = self.futile_logins.lock().unwrap();
+
+        if restored {
+            *futile_logins = FutileLogins { generation, count: 0 };
+        } else if futile_logins.generation != generation {
+            futile_logins.generation = generation;
+            futile_logins.count += 1;
+            if futile_logins.count == MAX_FUTILE_LOGINS {
+                tracing::error!("Logging in did not restore the session {} times in a row, not logging in again", MAX_FUTILE_LOGINS);
+            }
+        }
+    }
+
+    /// Logs in, unless another task already did since the given session was current.
+    pub async fn login(&self, client: &Client, lost_generation: u64) -> Result<(), AuthError> {
+        let _logging_in = self.logging_in.lock().await;
+        if self.generation() != lost_generation {
+            return Ok(());
+        }
+
+        let mut fields = self.fields.clone();
+        if let Some(form_page) = &self.form_page {
+            let page = client.get(form_page.clone()).send().await?.text().await?;
+            for (name, value) in hidden_fields(&page) {
+                fields.entry(name).or_insert(value);
+            }
+        }
+
+        let response = client.post(self.url.clone()).form(&fields).send().await?;
+        let status = response.status();
+        if status.is_client_error() || status.is_server_error() {
+            return Err(AuthError::new(&format!("Login to {} failed with status {}", self.url, status)));
+        }
+
+        // Failed logins usually show the form again
+        let body = response.text().await?;
+        if self.logged_out.as_ref().is_some_and(|logged_out| logged_out.is_match(&body)) {
+            return Err(AuthError::new(&format!("Login to {} was rejected", self.url)));
+        }
+
+        self.generation.fetch_add(1, Ordering::Release);
+        Ok(())
+    }
+
+    /// Whether a response shows that the session was lost: a 401, a redirect to the login, or a logged out page.
+    pub fn is_logged_out(&self, url: &Url, status: StatusCode, headers: &[(String, String)], body: &str) -> bool {
+        let is_login_page = |page: &Url| page.path() == self.url.path() || self.form_page.as_ref().is_some_and(|form| page.path() == form.path());
+        if is_login_page(url) {
+            return false;
+        }
+
+        if status == StatusCode::UNAUTHORIZED {
+            return true;
+        }
+
+        let redirects_to_login = status.is_redirection()
+            && headers
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(header::LOCATION.as_str()))
+                .and_then(|(_, location)| url.join(location).ok())
+                .is_some_and(|location| is_login_page(&location));
+
+        redirects_to_login || self.logged_out.as_ref().is_some_and(|logged_out| logged_out.is_match(body))
+    }
+}
+
+/// The names and values of the hidden inputs of a page.
+fn hidden_fields(page: &str) -> Vec<(String, String)> {
+    let document = Html::parse_document(page);
+    let selector = Selector::parse("input[type=hidden][name]").unwrap();
+
+    document
+        .select(&selector)
+        .filter_map(|input| {
+            let name = input.value().attr("name")?;
+            Some((name.to_string(), input.value().attr("value").unwrap_or_default().to_string()))
+        })
+        .collect()
+}
+
+#[derive(Debug)]
+pub struct AuthError {
+    message: String,
+}
+
+impl AuthError {
+    fn new(message: &str) -> AuthError {
+        AuthError {
+            message: message.to_string(),
+        }
+    }
+}
+
+impl From<reqwest::Error> for AuthError {
+    fn from(error: reqwest::Error) -> Self {
+        AuthError::new(&format!("Login request failed: {}", error))
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
@@ -1,43 +1,232 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::File;
 use std::hash::Hash;
+use std::io::{self, BufRead, BufReader};
+use std::net::IpAddr;
+use std::path::Path;
+
+use ipnet::IpNet;
+use url::Url;
 
 use crate::web::host::Host;
 
+/// The largest CIDR range, in addresses, that is expanded into targets
+const MAX_CIDR_HOSTS: u64 = 65536;
+
 /// A crawl target
-#[derive(Debug, Eq, Clone, Hash)]
+///
+/// A target without a scheme is a host whose services are still to be probed. Its port, if any, is the only one to
+/// probe. Once probed, every live service becomes a target of its own with a scheme and port.
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
 pub struct CrawlTarget {
-    host: Host // The target host
+    scheme: Option<String>, // The scheme used to reach the target, http or https
+    host: Host,             // The target host
+    port: Option<u16>,      // The port the target is reached on
+    path: String,           // The path crawling starts from
 }
 
 impl CrawlTarget {
+    /// Creates a target whose services are probed on the configured ports.
     pub fn new(host: Host) -> CrawlTarget {
+        CrawlTarget {
+            scheme: None,
+            host,
+            port: None,
+            path: "/".to_string(),
+        }
+    }
 
-        match host
-        {
-            Host::Domain(host) => {
-                CrawlTarget {
-                    host: Host::Domain(host), 
-                }
-            },
+    /// Creates a target from the URL crawling starts from.
+    pub fn from_url(url: &Url) -> Result<CrawlTarget, TargetParseError> {
+        if url.scheme() != "http" && url.scheme() != "https" {
+            return Err(TargetParseError::new(url.as_str(), "only http and https are supported"));
+        }
+
+        let Some(host) = url.host() else { return Err(TargetParseError::new(url.as_str(), "missing host")); };
+        let Ok(host) = Host::try_from(host) else { return Err(TargetParseError::new(url.as_str(), "invalid host")); };
 
-            Host::Ipv4(ip) => CrawlTarget {
-                host: Host::Ipv4(ip), 
-            },
+        Ok(CrawlTarget {
+            scheme: Some(url.scheme().to_string()),
+            host,
+            port: url.port_or_known_default(),
+            path: url.path().to_string(),
+        })
+    }
 
-            Host::Ipv6(ip) => CrawlTarget {
-                host: Host::Ipv6(ip), 
+    /// Returns the target for a live service of this target's host, crawled from the same path.
+    pub fn with_service(&self, scheme: &str, port: u16) -> CrawlTarget {
+        CrawlTarget {
+            scheme: Some(scheme.to_string()),
+            host: self.host.clone(),
+            port: Some(port),
+            path: self.path.clone(),
+        }
+    }
+
+    /// Parses a line of a targets file into the targets it describes.
+    ///
+    /// Accepted are URLs, domain names, IPv4 and IPv6 addresses, `host:port` pairs and CIDR ranges, which expand to
+    /// one target per host address. Only URLs name a scheme, every other target is probed.
+    pub fn parse(target: &str) -> Result<Vec<CrawlTarget>, TargetParseError> {
+        let target = target.trim();
+
+        if target.contains("://") {
+            let url = Url::parse(target).map_err(|error| TargetParseError::new(target, &error.to_string()))?;
+            return Ok(vec![Self::from_url(&url)?]);
+        }
+
+        if let Ok(network) = target.parse::<IpNet>() {
+            let addresses = 2u64.saturating_pow(u32::from(network.max_prefix_len() - network.prefix_len()));
+            if addresses > MAX_CIDR_HOSTS {
+                return Err(TargetParseError::new(target, &format!("ranges are limited to {} addresses", MAX_CIDR_HOSTS)));
             }
+
+            return Ok(network.hosts().map(|ip| CrawlTarget::new(ip.into())).collect());
         }
 
+        if let Ok(ip) = target.parse::<IpAddr>() {
+            return Ok(vec![CrawlTarget::new(ip.into())]);
+        }
+
+        // Domain names and host:port pairs. The URL parser drops the default port, so it is looked for explicitly.
+        let url = Url::parse(&format!("https://{}", target)).map_err(|error| TargetParseError::new(target, &error.to_string()))?;
+        let Some(host) = url.host() else { return Err(TargetParseError::new(target, "missing host")); };
+        let Ok(host) = Host::try_from(host) else { return Err(TargetParseError::new(target, "invalid host")); };
+
+        let authority = target.split('/').next().unwrap_or_default();
+        let mut crawl_target = CrawlTarget::new(host);
+        crawl_target.port = url.port().or_else(|| authority.ends_with(":443").then_some(443));
+        crawl_target.path = url.path().to_string();
+
+        Ok(vec![crawl_target])
     }
 
     /// Returns the host of the crawl target
     pub fn host(&self) -> &Host {
         &self.host
     }
+
+    /// Returns the scheme of the crawl target, if its services have been probed
+    pub fn scheme(&self) -> Option<&str> {
+        self.scheme.as_deref()
+    }
+
+    /// Returns the port of the crawl target, if known
+    pub fn port(&self) -> Option<u16> {
+        self.port
+    }
+
+    /// Returns the URL crawling of the target starts from, assuming HTTPS if it has not been probed
+    pub fn url(&self) -> Url {
+        let scheme = self.scheme.as_deref().unwrap_or("https");
+        let mut url = Url::parse(&format!("{}://{}/", scheme, self.authority())).expect("a valid host makes a valid URL");
+        url.set_path(&self.path);
+        url
+    }
+
+    /// Returns the host and port as used in URLs
+    fn authority(&self) -> String {
+        let host = match &self.host {
+            Host::Ipv6(ip) => format!("[{}]", ip),
+            host => host.to_string(),
+        };
+
+        match self.port {
+            Some(port) => format!("{}:{}", host, port),
+            None => host,
+        }
+    }
 }
 
-impl PartialEq for CrawlTarget {
-    fn eq(&self, other: &Self) -> bool {
-        self.host == other.host
+/// Reads the targets listed in a file, or in stdin if the path is `-`.
+///
+/// Blank lines and `#` comments are skipped, as is every line that is not a valid target, after reporting it.
+pub fn read_targets(path: &Path) -> Result<HashSet<CrawlTarget>, io::Error> {
+    let reader: Box<dyn BufRead> = if path == Path::new("-") {
+        Box::new(io::stdin().lock())
+    } else {
+        Box::new(BufReader::new(File::open(path)?))
+    };
+
+    let mut targets = HashSet::new();
+    for line in reader.lines() {
+        let line = line?;
+        let target = strip_comment(&line).trim();
+        if target.is_empty() {
+            continue;
+        }
+
+        match CrawlTarget::parse(target) {
+            Ok(parsed) => targets.extend(parsed),
+            Err(error) => tracing::warn!("{}", error),
+        }
+    }
+
+    Ok(targets)
+}
+
+/// Removes a comment from a line of a targets file. A `#` only starts a comment at the beginning of the line or after
+/// whitespace, so URL fragments survive.
+fn strip_comment(line: &str) -> &str {
+    let comment = line
+        .char_indices()
+        .find(|&(index, c)| c == '#' && line[..index].chars().next_back().is_none_or(char::is_whitespace));
+
+    match comment {
+        Some((index, _)) => &line[..index],
+        None => line,
+    }
+}
+
+impl fmt::Display for CrawlTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.scheme {
+            Some(_) => write!(f, "{}", self.url()),
+            None => write!(f, "{}", self.authority()),
+        }
+    }
+}
+
+/// How a target was discovered, recorded with its host
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiscoverySource {
+    Target, // Given to the crawler
+    Probe,  // A service found by probing a host
+    Link,   // Linked from a crawled page
+    TlsSan, // Named by the certificate of a crawled host
+}
+
+impl DiscoverySource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DiscoverySource::Target => "target",
+            DiscoverySource::Probe => "probe",
+            DiscoverySource::Link => "link",
+            DiscoverySource::TlsSan => "tls-san",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct TargetParseError {
+    target: String,
+    reason: String,
+}
+
+impl TargetParseError {
+    fn new(target: &str, reason: &str) -> TargetParseError {
+        TargetParseError {
+            target: target.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+}
+
+impl std::error::Error for TargetParseError {}
+
+impl fmt::Display for TargetParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid target {}: {}", self.target, self.reason)
     }
 }
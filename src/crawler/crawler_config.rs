@@ -1,11 +1,54 @@
-use std::{collections::HashSet, path::PathBuf};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
 
-use crate::CrawlTarget;
+use reqwest::cookie::Jar;
+
+use crate::web::{host::Host, http::Timeouts, tls::TlsOptions};
+
+use super::{
+    auth::{HostAuth, Login},
+    crawl_target::CrawlTarget,
+    proxy::ProxyRoutes,
+    scope::Scope,
+};
 
 
 #[derive(Debug)]
 pub struct CrawlerConfig {
     pub initial_targets: HashSet<CrawlTarget>,
+    pub excluded_hosts: HashSet<Host>,
+    pub scope: Scope,
     pub crawl_subdomains: bool,
-    pub db_path: PathBuf
+    pub probe_ports: Vec<u16>,
+    pub max_concurrent_requests: usize,
+    pub request_delay: Duration,
+    /// Bodies are downloaded up to this many bytes, and truncated beyond
+    pub max_body_size: usize,
+    pub timeouts: Timeouts,
+    pub max_retries: u32,
+    /// The wait before the first retry, doubled before each of the next ones
+    pub retry_backoff: Duration,
+    pub headers: Vec<(String, String)>,
+    /// Shared by every request, so cookies set during the crawl are sent back
+    pub cookies: Arc<Jar>,
+    pub auth: HostAuth,
+    pub login: Option<Login>,
+    /// Shared with the web client, which asks it for the proxy of every request
+    pub proxies: Arc<ProxyRoutes>,
+    pub tls: TlsOptions,
+    pub disabled_analyzers: HashSet<String>,
+    pub db_path: PathBuf,
+    pub max_stored_body_size: usize,
+    pub train_body_dictionary: bool,
+    pub jsonl_output: Option<PathBuf>,
+    pub warc_output: Option<PathBuf>,
+    pub warc_max_file_size: u64,
+    /// The effective settings, recorded with the session so that it can be resumed
+    pub session_settings: String,
+    /// URLs fetched by earlier sessions and the links found on them, which are followed without fetching again
+    pub fetched_urls: HashMap<String, Vec<String>>
 }
\ No newline at end of file
@@ -0,0 +1,180 @@
+use std::error::Error;
+use std::fmt::Write as _;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use super::stats::CrawlStats;
+use crate::output::{OutputRecord, OutputSink};
+
+/// The upper bounds of the histogram buckets, in seconds
+const BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// The largest request head read from a metrics client
+const MAX_REQUEST_SIZE: usize = 8192;
+
+/// How long a metrics client may take to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long to wait before accepting connections again after failing to, such as when out of file handles
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(500);
+
+/// A Prometheus histogram of durations
+#[derive(Debug, Clone, Default)]
+pub struct Histogram {
+    counts: [u64; BUCKETS.len()], // Not cumulative, unlike the exposed buckets
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    pub fn observe(&mut self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        if let Some(bucket) = BUCKETS.iter().position(|&bound| seconds <= bound) {
+            self.counts[bucket] += 1;
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let separator = if labels.is_empty() { "" } else { "," };
+        let mut cumulative = 0;
+        for (bound, count) in BUCKETS.iter().zip(self.counts) {
+            cumulative += count;
+            let _ = writeln!(out, "{}_bucket{{{}{}le=\"{}\"}} {}", name, labels, separator, bound, cumulative);
+        }
+        let _ = writeln!(out, "{}_bucket{{{}{}le=\"+Inf\"}} {}", name, labels, separator, self.count);
+
+        let labels = if labels.is_empty() { String::new() } else { format!("{{{}}}", labels) };
+        let _ = writeln!(out, "{}_sum{} {}", name, labels, self.sum);
+        let _ = writeln!(out, "{}_count{} {}", name, labels, self.count);
+    }
+}
+
+/// Times the batches written to a sink
+pub struct TimedSink {
+    sink: Box<dyn OutputSink>,
+    stats: Arc<CrawlStats>,
+}
+
+impl TimedSink {
+    pub fn new(sink: Box<dyn OutputSink>, stats: Arc<CrawlStats>) -> TimedSink {
+        TimedSink { sink, stats }
+    }
+}
+
+impl OutputSink for TimedSink {
+    fn write_batch(&mut self, batch: &[OutputRecord]) -> Result<(), Box<dyn Error>> {
+        let started = Instant::now();
+        let result = self.sink.write_batch(batch);
+        self.stats.db_write(started.elapsed());
+        result
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), Box<dyn Error>> {
+        self.sink.finish()
+    }
+}
+
+/// Serves the metrics of a crawl at `/metrics`, in the Prometheus text format, until aborted.
+pub async fn serve(listener: TcpListener, stats: Arc<CrawlStats>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(respond(stream, Arc::clone(&stats)));
+            }
+            Err(error) => {
+                tracing::warn!("Failed to accept a metrics connection: {}", error);
+                tokio::time::sleep(ACCEPT_BACKOFF).await;
+            }
+        }
+    }
+}
+
+async fn respond(mut stream: TcpStream, stats: Arc<CrawlStats>) {
+    // Only the request line matters, but the whole head is read so the client sees a clean close
+    let mut request = Vec::new();
+    let mut buffer = [0; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") && request.len() < MAX_REQUEST_SIZE {
+        match tokio::time::timeout(REQUEST_TIMEOUT, stream.read(&mut buffer)).await {
+            Ok(Ok(read)) if read > 0 => request.extend_from_slice(&buffer[..read]),
+            _ => return,
+        }
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let mut request_line = request.lines().next().unwrap_or_default().split_whitespace();
+    let (method, path) = (request_line.next().unwrap_or_default(), request_line.next().unwrap_or_default());
+
+    let (status, body) = if method == "GET" && path.split('?').next() == Some("/metrics") {
+        ("200 OK", render(&stats))
+    } else {
+        ("404 Not Found", "Not found\n".to_string())
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+/// Renders the metrics in the Prometheus text format.
+pub fn render(stats: &CrawlStats) -> String {
+    let mut out = String::new();
+
+    header(&mut out, "requests_total", "counter", "Requests sent");
+    let _ = writeln!(out, "cherna_vdovitsa_requests_total {}", stats.requests.load(Ordering::Relaxed));
+
+    header(&mut out, "responses_total", "counter", "Responses received, by status code");
+    for (status, count) in stats.statuses() {
+        let _ = writeln!(out, "cherna_vdovitsa_responses_total{{status=\"{}\"}} {}", status, count);
+    }
+
+    header(&mut out, "errors_total", "counter", "Failed requests and writes, by kind");
+    for (kind, count) in stats.errors_by_kind() {
+        let _ = writeln!(out, "cherna_vdovitsa_errors_total{{kind=\"{}\"}} {}", escape(kind), count);
+    }
+
+    header(&mut out, "retries_total", "counter", "Requests sent again after a timeout, a dropped connection or a gateway error");
+    let _ = writeln!(out, "cherna_vdovitsa_retries_total {}", stats.retries.load(Ordering::Relaxed));
+
+    header(&mut out, "downloaded_bytes_total", "counter", "Bytes of response bodies downloaded");
+    let _ = writeln!(out, "cherna_vdovitsa_downloaded_bytes_total {}", stats.downloaded_bytes.load(Ordering::Relaxed));
+
+    header(&mut out, "in_flight_requests", "gauge", "Requests waiting for a response");
+    let _ = writeln!(out, "cherna_vdovitsa_in_flight_requests {}", stats.in_flight.load(Ordering::Relaxed));
+
+    header(&mut out, "frontier_size", "gauge", "Requests queued for their turn");
+    let _ = writeln!(out, "cherna_vdovitsa_frontier_size {}", stats.frontier());
+
+    header(&mut out, "active_targets", "gauge", "Targets being crawled");
+    let _ = writeln!(out, "cherna_vdovitsa_active_targets {}", stats.active_targets().len());
+
+    header(&mut out, "request_duration_seconds", "histogram", "Time until the response headers arrived, by host");
+    for (host, histogram) in stats.latencies() {
+        histogram.render(&mut out, "cherna_vdovitsa_request_duration_seconds", &format!("host=\"{}\"", escape(&host)));
+    }
+
+    header(&mut out, "db_write_duration_seconds", "histogram", "Time to write a batch of records to the database");
+    stats.db_writes().render(&mut out, "cherna_vdovitsa_db_write_duration_seconds", "");
+
+    out
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP cherna_vdovitsa_{} {}", name, help);
+    let _ = writeln!(out, "# TYPE cherna_vdovitsa_{} {}", name, kind);
+}
+
+/// Escapes a label value.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
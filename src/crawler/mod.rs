@@ -1,48 +1,110 @@
+pub mod auth;
 pub mod crawl_target;
 pub mod crawler_config;
+pub mod metrics;
+pub mod pipeline;
+pub mod proxy;
+pub mod reprocess;
+pub mod scope;
+pub mod stats;
 
 use core::fmt;
-use std::fs::{self, File};
-use std::sync::{Arc, Mutex};
-use std::{collections::HashSet};
-
-use reqwest::{header, Client, Url};
-use rusqlite::{params, Connection};
-use scraper::{Html, Selector};
-use tokio::sync::mpsc;
+use std::collections::HashSet;
+use std::future::Future;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Instant;
+
+use chrono::SecondsFormat;
+use encoding_rs::Encoding;
+use reqwest::{
+    cookie::CookieStore,
+    header::{self, HeaderMap, HeaderName, HeaderValue},
+    redirect,
+    tls::TlsInfo,
+    Client, StatusCode, Url,
+};
+use tokio::sync::{mpsc, Semaphore, SemaphorePermit};
+use tracing::{debug, error, info, info_span, warn, Instrument};
 
 use crate::{
-    util::ChannelPacket,
+    db::sink::DatabaseSink,
+    dns::domain_name::DomainName,
+    output::{jsonl::JsonLinesSink, warc::WarcSink, FetchedUrl, OutputHandle, OutputRecord, OutputSink, OutputWriter},
+    util::{redact, ChannelPacket},
     web::{
         host::{Host, HostRelationship},
-        http,
+        charset,
+        http::{self, FetchError},
+        tls::{self, CertificateInfo, PresentedChains},
     },
 };
-use crawl_target::CrawlTarget;
+use crawl_target::{CrawlTarget, DiscoverySource};
+
+use self::{auth::Login, crawler_config::CrawlerConfig, metrics::TimedSink, stats::CrawlStats};
 
-use self::crawler_config::CrawlerConfig;
 
 pub struct Crawler {
     crawl_targets: HashSet<CrawlTarget>,
     client: Client,
     config: Arc<CrawlerConfig>,
+    stats: Arc<CrawlStats>,
+    certificate_chains: Arc<PresentedChains>,
+}
+
+/// Where crawl tasks send the targets they discover
+type NewTargets = mpsc::Sender<ChannelPacket<(CrawlTarget, DiscoverySource)>>;
+
+/// What every task of a crawl shares
+struct CrawlContext {
+    client: Client,
+    output: OutputHandle,
+    config: Arc<CrawlerConfig>,
+    requests: Semaphore, // One permit per request allowed in flight
+    stats: Arc<CrawlStats>,
+    certificate_chains: Arc<PresentedChains>,
+    /// Weak so that the crawl still ends once every target task is done
+    new_targets: mpsc::WeakSender<ChannelPacket<(CrawlTarget, DiscoverySource)>>,
 }
 
 impl Crawler {
     /// Create a Vdovitsa crawler with initial targets.
     pub fn new(config: CrawlerConfig) -> Result<Crawler, CrawlerError> {
         // Configure the web client
-        let client_config = Client::builder().user_agent(concat!(
-            env!("CARGO_PKG_NAME"),
-            "/",
-            env!("CARGO_PKG_VERSION")
-        ));
+        let mut default_headers = HeaderMap::new();
+        for (name, value) in &config.headers {
+            let (Ok(name), Ok(value)) = (HeaderName::try_from(name), HeaderValue::try_from(value)) else {
+                return Err(CrawlerError::with_message(&format!("Invalid header: {}: {}", name, value)));
+            };
+            default_headers.insert(name, value);
+        }
+
+        // The TLS configuration hands over the certificate chain of every host it connects to, to be recorded
+        let certificate_chains = Arc::new(PresentedChains::default());
+        let tls_config = tls::client_config(&config.tls, Arc::clone(&certificate_chains))
+            .map_err(|error| CrawlerError::with_message(&error.to_string()))?;
+
+        let mut client_config = Client::builder()
+            .use_preconfigured_tls(tls_config)
+            .user_agent(http::USER_AGENT)
+            .default_headers(default_headers)
+            .cookie_provider(Arc::clone(&config.cookies))
+            .redirect(redirect::Policy::none())
+            .tls_info(true)
+            .connect_timeout(config.timeouts.connect)
+            .timeout(config.timeouts.total);
+
+        for proxy in config.proxies.proxies() {
+            client_config = client_config.proxy(proxy);
+        }
 
         if let Ok(client) = client_config.build() {
             Ok(Crawler {
                 crawl_targets: config.initial_targets.clone(),
                 client,
                 config: Arc::new(config),
+                stats: Arc::new(CrawlStats::new()),
+                certificate_chains,
             })
         } else {
             Err(CrawlerError::with_message(
@@ -51,210 +113,670 @@ impl Crawler {
         }
     }
 
+    /// Returns the live statistics of the crawl.
+    pub fn stats(&self) -> Arc<CrawlStats> {
+        Arc::clone(&self.stats)
+    }
+
     pub async fn crawl(&mut self) {
-        let (tx, mut new_targets) = mpsc::channel::<ChannelPacket<CrawlTarget>>(64);
+        let (tx, mut new_targets) = mpsc::channel::<ChannelPacket<(CrawlTarget, DiscoverySource)>>(64);
 
-        // Set up URLs table
-        let Ok(db) = Connection::open(&self.config.db_path) else { eprintln!("Failed to open DB!"); return; };
+        if let Some(login) = &self.config.login {
+            if let Err(error) = login.login(&self.client, login.generation()).await {
+                error!("{}", error);
+                return;
+            }
+            info!("Logged in");
+        }
 
-        db.execute("CREATE TABLE IF NOT EXISTS urls (
-            id INTEGER PRIMARY KEY,
-            url TEXT NOT NULL,
-            target TEXT NOT NULL,
-            response_code INTEGER,
-            response_body BLOB)
-            ", ()).unwrap();
+        // All output goes through a single writer
+        let Some(sinks) = self.open_sinks() else { return; };
+        let (output, output_writer) = OutputWriter::spawn(sinks);
 
-        db.close().unwrap();
+        let context = Arc::new(CrawlContext {
+            client: self.client.clone(),
+            output,
+            config: Arc::clone(&self.config),
+            requests: Semaphore::new(self.config.max_concurrent_requests),
+            stats: Arc::clone(&self.stats),
+            certificate_chains: Arc::clone(&self.certificate_chains),
+            new_targets: tx.downgrade(),
+        });
 
         // Start crawling the initial targets
+        let config = Arc::clone(&self.config);
+        self.crawl_targets.retain(|target| Self::is_allowed(&config, target));
         for target in &self.crawl_targets {
-            tokio::spawn(Self::crawl_target(
-                self.client.clone(),
-                target.clone(),
-                tx.clone(),
-                Arc::clone(&self.config),
-            ));
+            tokio::spawn(
+                Self::crawl_target(Arc::clone(&context), target.clone(), DiscoverySource::Target, tx.clone())
+                    .instrument(target_span(target)),
+            );
         }
 
         drop(tx);
 
         // Process new potential targets
-        while let Some(new_potential_target) = new_targets.recv().await {
-            if self.crawl_targets.insert(new_potential_target.data.clone()) {
-                tokio::spawn(Self::crawl_target(
-                    self.client.clone(),
-                    new_potential_target.data,
-                    new_potential_target.sender,
-                    Arc::clone(&self.config),
-                ));
+        while let Some(ChannelPacket { sender, data: (new_target, source) }) = new_targets.recv().await {
+            if self.stats.is_stopping() || !Self::is_allowed(&self.config, &new_target) {
+                continue;
+            }
+
+            if self.crawl_targets.insert(new_target.clone()) {
+                self.stats.discovered(new_target.to_string());
+                let span = target_span(&new_target);
+                tokio::spawn(Self::crawl_target(Arc::clone(&context), new_target, source, sender).instrument(span));
+            }
+        }
+
+        // Wait for the writer to flush everything that is still queued
+        drop(context);
+        if let Err(error) = output_writer.await {
+            error!("Output writer failed: {}", error);
+        }
+
+        info!("Crawling done");
+    }
+
+    /// Whether a target may be crawled. Targets still to be probed are checked against the scope once per service.
+    fn is_allowed(config: &CrawlerConfig, target: &CrawlTarget) -> bool {
+        !config.excluded_hosts.contains(target.host()) && (target.scheme().is_none() || config.scope.allows(&target.url()))
+    }
+
+    /// Opens the output database and any additional configured sinks.
+    fn open_sinks(&self) -> Option<Vec<Box<dyn OutputSink>>> {
+        let mut sinks: Vec<Box<dyn OutputSink>> = Vec::new();
+
+        match DatabaseSink::open(
+            &self.config.db_path,
+            self.config.max_stored_body_size,
+            self.config.train_body_dictionary,
+        ) {
+            Ok(sink) => {
+                let targets: Vec<String> = self.crawl_targets.iter().map(CrawlTarget::to_string).collect();
+                if let Err(error) = sink.describe_session(&self.config.session_settings, &targets) {
+                    error!("Failed to update DB: {}", error);
+                    return None;
+                }
+                sinks.push(Box::new(TimedSink::new(Box::new(sink), Arc::clone(&self.stats))));
             }
+            Err(error) => { error!("Failed to open DB: {}", error); return None; }
         }
 
-        println!("Crawling done");
+        if let Some(jsonl_path) = &self.config.jsonl_output {
+            match JsonLinesSink::create(jsonl_path) {
+                Ok(sink) => sinks.push(Box::new(sink)),
+                Err(error) => { error!("Failed to open JSON Lines output: {}", error); return None; }
+            }
+        }
+
+        if let Some(warc_prefix) = &self.config.warc_output {
+            match WarcSink::create(warc_prefix, self.config.warc_max_file_size) {
+                Ok(sink) => sinks.push(Box::new(sink)),
+                Err(error) => { error!("Failed to open WARC output: {}", error); return None; }
+            }
+        }
+
+        Some(sinks)
     }
 
     async fn crawl_target(
-        client: Client,
+        context: Arc<CrawlContext>,
         crawl_target: CrawlTarget,
-        new_targets: mpsc::Sender<ChannelPacket<CrawlTarget>>,
-        config: Arc<CrawlerConfig>,
+        source: DiscoverySource,
+        new_targets: NewTargets,
     ) {
         let crawl_target_host = crawl_target.host().to_owned();
-        println!("Crawling target... {}", crawl_target_host);
-
-        let mut crawled_urls: HashSet<String> = HashSet::new();
-        crawled_urls.insert(format!("{}", crawl_target.host()).clone());
-
-        let (tx, mut new_links) = mpsc::channel::<ChannelPacket<HashSet<String>>>(64);
 
-        // Create DB table for the target
-        let Ok(db) = Connection::open(&config.db_path) else { eprintln!("Failed to create database table for: {}", crawl_target_host); return;};
-        let db = Arc::new(Mutex::new(db));
+        // Record the target
+        let record = OutputRecord::Host { host: crawl_target_host.to_string(), source: source.as_str() };
+        if let Err(error) = context.output.write(record).await {
+            error!("Failed to update DB: {}", error);
+            return;
+        }
 
-        if let Ok(db) = db.lock() {
-            if let Err(err) = db.execute("INSERT INTO targets (host) VALUES (?1)", params![crawl_target_host.to_string()]) {
-                eprintln!("Failed to update DB: {}", err);
+        // Find the services of a host before crawling them as targets of their own
+        if crawl_target.scheme().is_none() {
+            if context.stats.is_stopping() {
                 return;
             }
-        }
-        else {
-            eprintln!("Failed to acquire lock on database.");
+
+            for service in Self::probe_target(&context, &crawl_target).await {
+                let packet = ChannelPacket { sender: new_targets.clone(), data: (service, DiscoverySource::Probe) };
+                if new_targets.send(packet).await.is_err() {
+                    return;
+                }
+            }
             return;
         }
 
-        // Crawl the target host's main page
-        tokio::spawn(Self::crawl_url(
-            client.clone(),
-            Url::parse(&format!("https://{}/", crawl_target_host)).unwrap(),
-            tx.clone(),
-            Arc::clone(&db),
-        ));
+        let start_url = crawl_target.url();
+        let origin = start_url.origin().ascii_serialization();
+        let target_stats = context.stats.target(&origin);
+        info!("Crawling target");
+
+        let mut crawled_urls: HashSet<String> = HashSet::new();
+        crawled_urls.insert(start_url.to_string());
+
+        let (tx, mut new_links) = mpsc::channel::<ChannelPacket<HashSet<String>>>(64);
+
+        // Crawl the target's starting page
+        tokio::spawn(
+            Self::crawl_url(Arc::clone(&context), start_url.clone(), tx.clone(), crawl_target_host.to_string())
+                .instrument(url_span(&start_url)),
+        );
 
         drop(tx);
 
         while let Some(new_potential_links) = new_links.recv().await {
+            // Links arrive resolved against the page they were found on
             for link in new_potential_links.data {
-                // If the URL is relative
-                if link.starts_with('/') && link.len() > 1 {
-                    let absolute_link = format!("{}{}", crawl_target.host().to_string(), link);
-
-                    if crawled_urls.insert(absolute_link.clone()) {
-                        tokio::spawn(Self::crawl_url(
-                            client.clone(),
-                            Url::parse(&format!("https://{}", absolute_link)).unwrap(),
-                            new_potential_links.sender.clone(),
-                            Arc::clone(&db),
-                        ));
+                // The links of a skipped target are drained without being followed
+                if context.stats.is_skipped(&origin) {
+                    break;
+                }
+
+                let Ok(mut parsed_url) = Url::parse(&link) else { continue; };
+                parsed_url.set_fragment(None);
+
+                // Only HTTP and HTTPS are supported
+                if parsed_url.scheme().eq("https") || parsed_url.scheme().eq("http") {
+                    let Some(parsed_url_host) = parsed_url.host() else { continue; };
+                    let Ok(parsed_url_host) = Host::try_from(parsed_url_host) else { continue; };
+
+                    let relationship = Host::host_relationship(crawl_target.host(), &parsed_url_host);
+                    if relationship != HostRelationship::Unrelated && !context.config.scope.allows(&parsed_url) {
+                        continue;
                     }
-                } else {
-                    let Ok(parsed_url) = Url::parse(&link) else { continue; };
-                    // Only HTTP and HTTPS are supported
-
-                    if parsed_url.scheme().eq("https") || parsed_url.scheme().eq("http") {
-                        let Some(parsed_url_host) = parsed_url.host() else { continue; };
-                        let Ok(parsed_url_host) = Host::try_from(parsed_url_host) else { continue; };
-
-                        match Host::host_relationship(crawl_target.host(), &parsed_url_host) {
-                            // A new link to crawl
-                            HostRelationship::Same => {
-                                if crawled_urls.insert(parsed_url.to_string()) {
-                                    tokio::spawn(Self::crawl_url(
-                                        client.clone(),
-                                        parsed_url.clone(),
+
+                    match relationship {
+                        // A new link to crawl
+                        HostRelationship::Same if parsed_url.origin() == start_url.origin() => {
+                            if crawled_urls.insert(parsed_url.to_string()) {
+                                let span = url_span(&parsed_url);
+                                tokio::spawn(
+                                    Self::crawl_url(
+                                        Arc::clone(&context),
+                                        parsed_url,
                                         new_potential_links.sender.clone(),
-                                        Arc::clone(&db),
-                                    ));
-                                }
+                                        crawl_target_host.to_string(),
+                                    )
+                                    .instrument(span),
+                                );
                             }
+                        }
 
-                            // A new target to crawl
-                            HostRelationship::Related => {
-                                if config.crawl_subdomains {
-                                    new_targets
-                                        .send(ChannelPacket {
-                                            sender: new_targets.clone(),
-                                            data: CrawlTarget::new(parsed_url_host),
-                                        })
-                                        .await
-                                        .unwrap();
-                                }
+                        // A new target to crawl, starting from the root of the linked origin
+                        HostRelationship::Same | HostRelationship::Related => {
+                            if relationship == HostRelationship::Same || context.config.crawl_subdomains {
+                                let Ok(origin) = parsed_url.join("/") else { continue; };
+                                let Ok(new_target) = CrawlTarget::from_url(&origin) else { continue; };
+
+                                new_targets
+                                    .send(ChannelPacket {
+                                        sender: new_targets.clone(),
+                                        data: (new_target, DiscoverySource::Link),
+                                    })
+                                    .await
+                                    .unwrap();
                             }
+                        }
 
-                            HostRelationship::Unrelated => {
-                                continue;
-                            }
+                        HostRelationship::Unrelated => {
+                            continue;
                         }
                     }
                 }
             }
         }
 
-        println!("Finished crawling target: {}", crawl_target_host);
+        drop(target_stats);
+        context.stats.finish_target(&origin);
+        info!("Finished crawling target");
+    }
+
+    /// Tries every scheme on every probed port of a target, recording the outcomes, and returns the live services.
+    /// The services of a target worth probing: its own port or the configured probe ports, over HTTPS and HTTP,
+    /// leaving out those not in scope.
+    fn in_scope_services(context: &CrawlContext, crawl_target: &CrawlTarget) -> Vec<CrawlTarget> {
+        let ports = match crawl_target.port() {
+            Some(port) => vec![port],
+            None => context.config.probe_ports.clone(),
+        };
+
+        ports
+            .iter()
+            .flat_map(|&port| ["https", "http"].map(|scheme| crawl_target.with_service(scheme, port)))
+            .filter(|service| context.config.scope.allows(&service.url()))
+            .collect()
+    }
+
+    async fn probe_target(context: &CrawlContext, crawl_target: &CrawlTarget) -> Vec<CrawlTarget> {
+        // Services out of scope are not even probed
+        let probes = Self::in_scope_services(context, crawl_target).into_iter().map(|service| async move {
+            let _permit = context.wait_for_request().await;
+            let probed_at = chrono::Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+            let url = service.url();
+            let result = Self::send(context, &url, context.client.get(url.clone()).send()).await;
+            (service, probed_at, result)
+        });
+        let results = futures::future::join_all(probes).await;
+
+        let mut services = Vec::new();
+        for (service, probed_at, result) in &results {
+            let (status, error) = match result {
+                Ok(response) => (Some(response.status()), None),
+                Err((error, detail)) => (None, Some(format!("{}: {}", error, detail))),
+            };
+            // A port speaking HTTPS answers plain HTTP with an error such as a 400, which is not a service of its own.
+            // A certificate that does not verify still shows that the port speaks TLS.
+            let https = results.iter().find(|(other, _, _)| other.scheme() == Some("https") && other.port() == service.port());
+            let scheme_mismatch = service.scheme() == Some("http")
+                && match (status, https) {
+                    (Some(_), Some((_, _, Ok(_)))) => true,
+                    (Some(status), Some((_, _, Err((FetchError::Tls, _))))) => status == StatusCode::BAD_REQUEST,
+                    _ => false,
+                };
+
+            let record = OutputRecord::Service {
+                host: crawl_target.host().to_string(),
+                scheme: service.scheme().unwrap_or_default().to_string(),
+                port: service.port().unwrap_or_default(),
+                probed_at: probed_at.clone(),
+                status: status.map(|status| status.as_u16()),
+                error: if scheme_mismatch { Some("scheme mismatch: the port speaks HTTPS".to_string()) } else { error },
+            };
+            if let Err(error) = context.output.write(record).await {
+                error!("Failed to write output: {}", error);
+            }
+
+            if status.is_some() && !scheme_mismatch {
+                info!(service = %service, "Found service");
+                services.push(service.clone());
+            }
+        }
+
+        services
     }
 
     async fn crawl_url(
-        client: Client,
+        context: Arc<CrawlContext>,
         url: Url,
         new_links: mpsc::Sender<ChannelPacket<HashSet<String>>>,
-        db: Arc<Mutex<Connection>>
+        target: String,
     ) {
-        let mut new_links_to_crawl: HashSet<String> = HashSet::new();
-
-        // Send get request
-        let Ok(response) = http::get_url(&client, url.clone()).await else { return; };
-        
-        let status_code = response.status();
-        if !status_code.is_success() { return; }
-
-        // Check if the URL returns an HTML page
-        let Some(content_type) = response.headers().get(header::CONTENT_TYPE) else { return; };
-        let Ok(content_type) = content_type.to_str() else { return; };
-        if !content_type.starts_with("text/html") {
+        // Pages fetched by an earlier session are not fetched again, but their links are still followed
+        if let Some(links) = context.config.fetched_urls.get(url.as_str()) {
+            if !links.is_empty() {
+                let packet = ChannelPacket { sender: new_links.clone(), data: links.iter().cloned().collect() };
+                let _ = new_links.send(packet).await;
+            }
             return;
         }
 
-        if let Ok(response_text) = response.text().await {
-            // Check content for links
-            let document = Html::parse_document(&response_text);
-            let selector = Selector::parse("a").unwrap();
+        let origin = url.origin().ascii_serialization();
+        let target_stats = context.stats.target(&origin);
 
-            // Parse links from the webpage
-            for element in document.select(&selector) {
-                // Try to get the href attribute
-                if let Some(href) = element.value().attr("href") {
-                    new_links_to_crawl.insert(href.to_owned());
-                }
-            }
+        // The permit is held until the body has been downloaded
+        let queued = context.stats.queue(&target_stats);
+        let permit = context.wait_for_request().await;
+        drop(queued);
 
-            match db.lock() {
-                Ok(db) => {
-                    if let Err(error) = db.execute(
-                        "INSERT INTO urls (url, target, response_code, response_body) VALUES (?1, ?2, ?3, ?4)", 
-                        params![url.to_string(), url.host_str().unwrap(), status_code.as_u16(), response_text]
-                    ) {
-                        eprintln!("Failed to update DB: {}", error);
-                        return;
+        if context.stats.is_skipped(&origin) {
+            return;
+        }
+        let in_flight = context.stats.request(&target_stats);
+
+        // A lost session is noticed on the response, so the page is fetched again once logged in
+        let login_generation = context.config.login.as_ref().map(Login::generation);
+        let mut response = match Self::fetch(&context, &url).await {
+            Ok(response) => response,
+            Err(failed) => return Self::record_failure(&context, &url, failed).await,
+        };
+        if let (Some(login), Some(generation)) = (&context.config.login, login_generation) {
+            if login.may_login() && login.is_logged_out(&url, response.status, &response.headers, &response.text) {
+                warn!("Session lost, logging in again");
+                match login.login(&context.client, generation).await {
+                    Ok(()) => {
+                        response = match Self::fetch(&context, &url).await {
+                            Ok(response) => response,
+                            Err(failed) => return Self::record_failure(&context, &url, failed).await,
+                        };
+                        login.record_login_result(!login.is_logged_out(&url, response.status, &response.headers, &response.text));
                     }
-                }
-                Err(error) => {
-                    eprintln!("Failed to obtain mutex lock: {}", error);
-                    return;
+                    Err(error) => error!("{}", error),
                 }
             }
         }
+        let Response { fetched_at, started, wait_ms, method, range, status: status_code, http_version, headers, body, text, encoding, truncated } =
+            response;
+
+        drop(permit);
+        drop(in_flight);
+
+        let mut processed = pipeline::process_response(status_code, &headers, &text);
+        processed.findings.retain(|finding| !context.config.disabled_analyzers.contains(&finding.kind));
+        let links = processed.links(&url);
+
+        let mut request_headers = http::request_headers(&url);
+        request_headers.extend(context.config.headers.iter().cloned());
+        if let Some(range) = range {
+            request_headers.push(("range".to_string(), range));
+        }
+        if let Some(cookie) = context.config.cookies.cookies(&url) {
+            request_headers.push(("cookie".to_string(), String::from_utf8_lossy(cookie.as_bytes()).into_owned()));
+        }
+        // Credentials sent as headers are not kept with the response
+        let request_headers: Vec<(String, String)> = request_headers.into_iter().map(redact::header).collect();
+        let new_potential_links: HashSet<String> = links.iter().cloned().collect();
+
+        let record = OutputRecord::Response(Box::new(FetchedUrl {
+            url: url.to_string(),
+            target,
+            host: url.host_str().unwrap_or_default().to_string(),
+            method: method.to_string(),
+            status: status_code.as_u16(),
+            http_version,
+            request_headers,
+            fetched_at,
+            wait_ms,
+            duration_ms: started.elapsed().as_millis() as u64,
+            headers,
+            body,
+            encoding: encoding.map(|encoding| encoding.name().to_string()),
+            truncated,
+            page: processed.page,
+            links,
+            findings: processed.findings,
+        }));
+
+        if let Err(error) = context.output.write(record).await {
+            error!("Failed to write output: {}", error);
+            context.stats.error("output", format!("{}: {}", url, error));
+            return;
+        }
 
         // Send the new links to the parent crawl_target
-        if !new_links_to_crawl.is_empty() {
+        if !new_potential_links.is_empty() {
             new_links
                 .send(ChannelPacket {
                     sender: new_links.clone(),
-                    data: new_links_to_crawl,
+                    data: new_potential_links,
                 })
                 .await
                 .unwrap();
         }
     }
+    /// Sends a GET request and downloads the body if it is textual, retrying transient failures and counting the outcome.
+    async fn fetch(context: &CrawlContext, url: &Url) -> Result<Response, FailedFetch> {
+        let fetched_at = chrono::Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let can_retry = attempts <= context.config.max_retries;
+            match Self::fetch_once(context, url).await {
+                Ok(response) if can_retry && http::is_transient_status(response.status) => {
+                    warn!(status = response.status.as_u16(), attempts, "Transient response, retrying");
+                }
+                Ok(response) => return Ok(response),
+                Err((error, detail)) if can_retry && error.is_transient() => {
+                    warn!(attempts, "Request failed, retrying: {}", detail);
+                }
+                Err((error, detail)) => {
+                    warn!(attempts, "Request failed: {}", detail);
+                    context.stats.error(error.as_str(), format!("{}: {}", url, detail));
+                    return Err(FailedFetch { fetched_at, attempts, error, detail });
+                }
+            }
+
+            context.stats.retries.fetch_add(1, Ordering::Relaxed);
+            let backoff = context.config.retry_backoff.saturating_mul(1 << (attempts - 1).min(16));
+            tokio::time::sleep(backoff).await;
+        }
+    }
+
+    /// Sends a GET request once and downloads the body if it is textual, up to the maximum body size.
+    ///
+    /// Resources that look binary from their URL are asked for with a HEAD request first. Those that are not textual
+    /// are not downloaded at all, and of those too large only the beginning is asked for.
+    async fn fetch_once(context: &CrawlContext, url: &Url) -> Result<Response, (FetchError, String)> {
+        let max_body_size = context.config.max_body_size;
+        let credentials = context.config.auth.credentials(url);
+
+        let mut range = None;
+        if http::is_likely_binary(url) {
+            let fetched_at = chrono::Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+            let started = Instant::now();
+            let response = Self::send(context, url, http::head_url(&context.client, url.clone(), credentials)).await?;
+
+            // Servers that do not answer HEAD requests are asked for the resource itself
+            if !matches!(response.status(), StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED) {
+                if !http::is_textual(&header_value(response.headers(), header::CONTENT_TYPE)) {
+                    return Self::read_response(context, url, "HEAD", None, fetched_at, started, response).await;
+                }
+                if http::content_length(response.headers()).is_some_and(|length| length > max_body_size as u64) {
+                    range = Some(max_body_size);
+                }
+            }
+        }
+
+        let fetched_at = chrono::Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+        let started = Instant::now();
+        let response = match range {
+            Some(length) => Self::send(context, url, http::get_url_range(&context.client, url.clone(), credentials, length)).await?,
+            None => Self::send(context, url, http::get_url(&context.client, url.clone(), credentials)).await?,
+        };
+        Self::read_response(context, url, "GET", range, fetched_at, started, response).await
+    }
+
+    /// Waits for the response headers, as long as the timeouts allow.
+    async fn send(
+        context: &CrawlContext,
+        url: &Url,
+        request: impl Future<Output = Result<reqwest::Response, reqwest::Error>>,
+    ) -> Result<reqwest::Response, (FetchError, String)> {
+        // Until the headers arrive, the server has the read timeout to answer once connected
+        let timeouts = context.config.timeouts;
+        let response = tokio::time::timeout(timeouts.connect + timeouts.read, request).await;
+        let server_certificate = match &response {
+            Ok(Ok(response)) => response.extensions().get::<TlsInfo>().and_then(TlsInfo::peer_certificate),
+            _ => None,
+        };
+        Self::record_certificates(context, url, server_certificate).await;
+        match response {
+            Ok(Ok(response)) => Ok(response),
+            Ok(Err(error)) => Err((FetchError::classify(&error), error.to_string())),
+            Err(_) => Err((FetchError::Timeout, format!("No response within {} ms", (timeouts.connect + timeouts.read).as_millis()))),
+        }
+    }
+
+    /// Downloads the body of a response if it is textual, streaming it up to the maximum body size.
+    async fn read_response(
+        context: &CrawlContext,
+        url: &Url,
+        method: &'static str,
+        range: Option<usize>,
+        fetched_at: String,
+        started: Instant,
+        mut response: reqwest::Response,
+    ) -> Result<Response, (FetchError, String)> {
+        let timeouts = context.config.timeouts;
+        let max_body_size = context.config.max_body_size;
+        context.stats.latency(url.host_str().unwrap_or_default(), started.elapsed());
+        let wait_ms = started.elapsed().as_millis() as u64;
+
+        let status = response.status();
+        context.stats.status(status.as_u16());
+        debug!(method, status = status.as_u16(), wait_ms, "Response received");
+        let http_version = format!("{:?}", response.version());
+        let headers: Vec<(String, String)> = response
+            .headers()
+            .iter()
+            .map(|(name, value)| (name.to_string(), String::from_utf8_lossy(value.as_bytes()).into_owned()))
+            .collect();
+
+        // Only textual bodies are downloaded
+        let content_type = header_value(response.headers(), header::CONTENT_TYPE);
+        let mut body = Vec::new();
+        let mut truncated = range.is_some() && status == StatusCode::PARTIAL_CONTENT;
+        if http::is_textual(&content_type) {
+            loop {
+                match tokio::time::timeout(timeouts.read, response.chunk()).await {
+                    Ok(Ok(Some(chunk))) => {
+                        context.stats.downloaded_bytes.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+
+                        // The rest of the body is left unread, which closes the connection
+                        if body.len() + chunk.len() > max_body_size {
+                            body.extend_from_slice(&chunk[..max_body_size - body.len()]);
+                            truncated = true;
+                            break;
+                        }
+                        body.extend_from_slice(&chunk);
+                    }
+                    Ok(Ok(None)) => break,
+                    Ok(Err(error)) => return Err((FetchError::classify(&error), format!("Failed to download body: {}", error))),
+                    Err(_) => return Err((FetchError::Timeout, format!("No data received for {} ms", timeouts.read.as_millis()))),
+                }
+            }
+        }
+        if truncated {
+            info!(max_body_size, "Body truncated");
+        }
+        // Bodies are parsed as UTF-8, but kept as they were received
+        let (text, encoding) = if body.is_empty() {
+            (String::new(), None)
+        } else {
+            let (text, encoding) = charset::decode(&body, &content_type, url);
+            (text, Some(encoding))
+        };
+
+        let range = range.map(|length| format!("bytes=0-{}", length.saturating_sub(1)));
+        Ok(Response { fetched_at, started, wait_ms, method, range, status, http_version, headers, body, text, encoding, truncated })
+    }
+
+    /// Records why a URL could not be fetched.
+    async fn record_failure(context: &CrawlContext, url: &Url, failed: FailedFetch) {
+        let record = OutputRecord::Failure {
+            url: url.to_string(),
+            host: url.host_str().unwrap_or_default().to_string(),
+            fetched_at: failed.fetched_at,
+            attempts: failed.attempts,
+            error: failed.error,
+            detail: failed.detail,
+        };
+        if let Err(error) = context.output.write(record).await {
+            error!("Failed to write output: {}", error);
+        }
+    }
+
+    /// Records the certificate chain of the service of a URL, once, if it presented one. The server certificate of
+    /// the connection tells the chains of the ports of a host apart.
+    async fn record_certificates(context: &CrawlContext, url: &Url, server_certificate: Option<&[u8]>) {
+        if url.scheme() != "https" {
+            return;
+        }
+
+        let host = url.host_str().unwrap_or_default().trim_matches(['[', ']']);
+        let port = url.port_or_known_default().unwrap_or(443);
+        let Some(chain) = context.certificate_chains.take(host, port, server_certificate) else { return; };
+        if let Some(error) = &chain.error {
+            warn!("Certificate of {} does not verify: {}", host, error);
+        }
+
+        let certificates: Vec<CertificateInfo> = chain.certificates.iter().map(|der| CertificateInfo::parse(der)).collect();
+        if context.config.crawl_subdomains {
+            if let (Some(certificate), Some(Ok(url_host))) = (certificates.first(), url.host().map(Host::try_from)) {
+                Self::harvest_certificate_names(context, &url_host, certificate).await;
+            }
+        }
+
+        let record = OutputRecord::Certificates {
+            host: host.to_string(),
+            port,
+            seen_at: chrono::Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            chain: certificates,
+            error: chain.error,
+        };
+        if let Err(error) = context.output.write(record).await {
+            error!("Failed to write output: {}", error);
+        }
+    }
+
+    /// Sends the hosts named by a certificate that are related to the host which presented it as new targets.
+    async fn harvest_certificate_names(context: &CrawlContext, host: &Host, certificate: &CertificateInfo) {
+        // Only a running target task can present a certificate, so the channel is still open
+        let Some(new_targets) = context.new_targets.upgrade() else { return; };
+
+        for name in certificate.host_names() {
+            let Ok(domain_name) = DomainName::parse(&name) else { continue; };
+            let named_host = Host::Domain(domain_name);
+            if Host::host_relationship(host, &named_host) != HostRelationship::Related {
+                continue;
+            }
+
+            // A host none of whose services may be crawled is not worth a target
+            let named_target = CrawlTarget::new(named_host);
+            if Self::in_scope_services(context, &named_target).is_empty() {
+                continue;
+            }
+
+            debug!(host = %named_target.host(), "Found related host in certificate");
+            let packet = ChannelPacket { sender: new_targets.clone(), data: (named_target, DiscoverySource::TlsSan) };
+            if new_targets.send(packet).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// A response and its downloaded body
+struct Response {
+    fetched_at: String,
+    started: Instant,
+    wait_ms: u64,
+    method: &'static str,
+    range: Option<String>, // The Range header sent, if only the beginning of the body was asked for
+    status: StatusCode,
+    http_version: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    text: String,
+    encoding: Option<&'static Encoding>, // The encoding the body was decoded from, if there is one
+    truncated: bool,
+}
+
+/// The value of a response header, empty if it is missing or not text.
+fn header_value(headers: &HeaderMap, name: HeaderName) -> String {
+    headers.get(name).and_then(|value| value.to_str().ok()).unwrap_or_default().to_string()
+}
+
+/// A fetch that failed, after any retries
+struct FailedFetch {
+    fetched_at: String,
+    attempts: u32,
+    error: FetchError,
+    detail: String,
+}
+
+/// The span of everything done for a target
+fn target_span(target: &CrawlTarget) -> tracing::Span {
+    info_span!("target", target = %target)
+}
+
+/// The span of fetching and processing a URL, nested in the span of its target
+fn url_span(url: &Url) -> tracing::Span {
+    info_span!("url", url = %url)
+}
+
+impl CrawlContext {
+    /// Waits until another request may be sent, honouring the concurrency limit and the delay between requests.
+    async fn wait_for_request(&self) -> Option<SemaphorePermit<'_>> {
+        self.stats.wait_while_paused().await;
+        let permit = self.requests.acquire().await.ok()?;
+        if !self.config.request_delay.is_zero() {
+            tokio::time::sleep(self.config.request_delay).await;
+        }
+        Some(permit)
+    }
 }
 
 #[derive(Debug)]
@@ -263,12 +785,6 @@ pub struct CrawlerError {
 }
 
 impl CrawlerError {
-    fn new() -> CrawlerError {
-        CrawlerError {
-            message: String::from("Crawl Target Error"),
-        }
-    }
-
     fn with_message(message: &str) -> CrawlerError {
         CrawlerError {
             message: String::from(message),
@@ -0,0 +1,67 @@
+use std::collections::HashSet;
+
+use reqwest::{header, StatusCode};
+use scraper::{Html, Selector};
+use url::Url;
+
+use crate::{
+    analyzer::Finding,
+    web::html::{self, PageText},
+};
+
+/// What the crawler learns from a response
+#[derive(Debug, Default)]
+pub struct ProcessedResponse {
+    /// Links as they appear in the response, possibly relative
+    pub hrefs: HashSet<String>,
+    pub page: PageText,
+    pub findings: Vec<Finding>,
+}
+
+impl ProcessedResponse {
+    /// Returns the links resolved against the URL they were found on.
+    pub fn links(&self, url: &Url) -> Vec<String> {
+        self.hrefs.iter().filter_map(|href| url.join(href).ok()).map(String::from).collect()
+    }
+}
+
+/// Extract the links and page text from a response.
+///
+/// This is the single processing step shared by live crawls and offline reprocessing, so it must not touch the network.
+pub fn process_response(status: StatusCode, headers: &[(String, String)], body: &str) -> ProcessedResponse {
+    let mut processed = ProcessedResponse::default();
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(header_name, _)| header_name.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    };
+
+    // Redirects are not followed by the client, their target is crawled like any other link
+    if status.is_redirection() {
+        if let Some(location) = header(header::LOCATION.as_str()) {
+            processed.hrefs.insert(location.to_string());
+        }
+    }
+
+    // Only successful HTML pages are parsed
+    let content_type = header(header::CONTENT_TYPE.as_str()).unwrap_or_default();
+    if !status.is_success() || !content_type.starts_with("text/html") {
+        return processed;
+    }
+
+    let document = Html::parse_document(body);
+    let selector = Selector::parse("a").unwrap();
+
+    // Parse links from the webpage
+    for element in document.select(&selector) {
+        // Try to get the href attribute
+        if let Some(href) = element.value().attr("href") {
+            processed.hrefs.insert(href.to_owned());
+        }
+    }
+
+    processed.page = html::page_text(&document);
+
+    processed
+}
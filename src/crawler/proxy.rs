@@ -0,0 +1,196 @@
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use globset::{Glob, GlobMatcher};
+use ipnet::IpNet;
+use reqwest::Proxy;
+use url::Url;
+
+use crate::cli::settings::ProxySettings;
+
+/// The proxy schemes the web client can tunnel through
+const PROXY_SCHEMES: [&str; 4] = ["http", "https", "socks5", "socks5h"];
+
+/// Which proxy each request goes through: the first route matching its host, or else the default proxy
+///
+/// Every proxy is checked when the routes are built, so that a request a route sends through a proxy never falls back
+/// to connecting directly.
+#[derive(Debug, Default)]
+pub struct ProxyRoutes {
+    proxies: Vec<Proxy>,
+}
+
+#[derive(Debug)]
+struct RouteTable {
+    routes: Vec<Route>,
+    default: Option<usize>,
+}
+
+/// A route matches a host if all of its conditions do
+#[derive(Debug)]
+struct Route {
+    hosts: Option<GlobMatcher>,
+    cidr: Option<IpNet>,
+    proxy: Option<usize>, // The index of the proxy, None connecting directly
+}
+
+impl ProxyRoutes {
+    pub fn new(settings: &ProxySettings) -> Result<ProxyRoutes, ProxyError> {
+        let mut proxy_urls = Vec::new();
+        let mut add_proxy = |url: &str, username: Option<&str>, password: Option<&str>| -> Result<Option<usize>, ProxyError> {
+            let proxy = parse_proxy(url, username, password)?;
+            Ok(proxy.map(|proxy| {
+                proxy_urls.push(proxy);
+                proxy_urls.len() - 1
+            }))
+        };
+
+        let default = match &settings.url {
+            Some(url) => add_proxy(url, settings.username.as_deref(), settings.password.as_deref())?,
+            None => None,
+        };
+
+        let mut routes = Vec::new();
+        for route in settings.routes.iter().flatten() {
+            let hosts = match &route.hosts {
+                Some(hosts) => Some(
+                    Glob::new(&hosts.to_ascii_lowercase())
+                        .map_err(|error| ProxyError::new(&format!("Invalid proxy route hosts {}: {}", hosts, error)))?
+                        .compile_matcher(),
+                ),
+                None => None,
+            };
+            let cidr = match &route.cidr {
+                Some(cidr) => Some(
+                    cidr.parse()
+                        .map_err(|error| ProxyError::new(&format!("Invalid proxy route CIDR {}: {}", cidr, error)))?,
+                ),
+                None => None,
+            };
+
+            routes.push(Route {
+                hosts,
+                cidr,
+                proxy: add_proxy(&route.url, route.username.as_deref(), route.password.as_deref())?,
+            });
+        }
+
+        // Each proxy only intercepts the requests the routes pick it for, so a direct route is not overridden
+        let table = Arc::new(RouteTable { routes, default });
+        let mut proxies = Vec::new();
+        for (index, ParsedProxy { url, basic_auth }) in proxy_urls.into_iter().enumerate() {
+            let selected = Arc::clone(&table);
+            let intercept = url.clone();
+            let mut proxy = Proxy::custom(move |url| (selected.proxy_for(url) == Some(index)).then(|| intercept.clone()));
+            if let Some((username, password)) = basic_auth {
+                proxy = proxy.basic_auth(&username, &password);
+            }
+            proxies.push(proxy);
+        }
+
+        Ok(ProxyRoutes { proxies })
+    }
+
+    /// The proxies to give the web client, which together send every request through the proxy of its route.
+    pub fn proxies(&self) -> Vec<Proxy> {
+        self.proxies.clone()
+    }
+}
+
+impl RouteTable {
+    fn proxy_for(&self, url: &Url) -> Option<usize> {
+        let host = url.host_str().unwrap_or_default().trim_matches(['[', ']']).to_ascii_lowercase();
+        match self.routes.iter().find(|route| route.matches(&host)) {
+            Some(route) => route.proxy,
+            None => self.default,
+        }
+    }
+}
+
+impl Route {
+    fn matches(&self, host: &str) -> bool {
+        self.hosts.as_ref().is_none_or(|hosts| hosts.is_match(host))
+            // Domain names are not resolved, so CIDR conditions only match IP hosts
+            && self.cidr.as_ref().is_none_or(|cidr| host.parse::<IpAddr>().is_ok_and(|ip| cidr.contains(&ip)))
+    }
+}
+
+/// A proxy URL ready for the web client, which cannot fail to use it
+struct ParsedProxy {
+    url: Url,
+    /// The credentials of an HTTP proxy, which are sent even without a password
+    basic_auth: Option<(String, String)>,
+}
+
+/// Parses a proxy URL, or `direct` for none, with the credentials if given.
+///
+/// The web client resolves the address of a SOCKS proxy on every request and connects directly if it cannot, so it is
+/// resolved once here instead.
+fn parse_proxy(url: &str, username: Option<&str>, password: Option<&str>) -> Result<Option<ParsedProxy>, ProxyError> {
+    if url == "direct" {
+        return Ok(None);
+    }
+
+    let mut proxy = Url::parse(url).map_err(|error| ProxyError::new(&format!("Invalid proxy {}: {}", url, error)))?;
+    if !PROXY_SCHEMES.contains(&proxy.scheme()) {
+        return Err(ProxyError::new(&format!("Unsupported proxy scheme {}, expected one of {}", proxy.scheme(), PROXY_SCHEMES.join(", "))));
+    }
+    if proxy.host_str().is_none_or(str::is_empty) {
+        return Err(ProxyError::new(&format!("Invalid proxy {}: no host", url)));
+    }
+
+    // Credentials given separately replace those of the URL
+    let username = username.map(String::from).or_else(|| (!proxy.username().is_empty()).then(|| proxy.username().to_string()));
+    let password = password.map(String::from).or_else(|| proxy.password().map(String::from));
+    let _ = proxy.set_username("");
+    let _ = proxy.set_password(None);
+
+    let mut basic_auth = None;
+    if proxy.scheme().starts_with("socks5") {
+        let address = proxy
+            .socket_addrs(|| Some(1080))
+            .ok()
+            .and_then(|addresses| addresses.into_iter().next())
+            .ok_or_else(|| ProxyError::new(&format!("Cannot resolve the address of proxy {}", url)))?;
+        let _ = proxy.set_ip_host(address.ip());
+        let _ = proxy.set_port(Some(address.port()));
+
+        // The web client only reads SOCKS credentials from the URL, and only when there is a password
+        if let Some(username) = username {
+            let Some(password) = password.filter(|password| !password.is_empty()) else {
+                return Err(ProxyError::new(&format!("The credentials of SOCKS proxy {} need a password", url)));
+            };
+            proxy.set_username(&username).map_err(|_| ProxyError::new(&format!("Cannot set credentials on proxy {}", url)))?;
+            proxy.set_password(Some(&password)).map_err(|_| ProxyError::new(&format!("Cannot set credentials on proxy {}", url)))?;
+        }
+    } else if let Some(username) = username {
+        basic_auth = Some((username, password.unwrap_or_default()));
+    }
+
+    // What the web client would reject, such as an invalid port, is rejected now rather than on the first request
+    Proxy::all(proxy.as_str()).map_err(|error| ProxyError::new(&format!("Invalid proxy {}: {}", url, error)))?;
+
+    Ok(Some(ParsedProxy { url: proxy, basic_auth }))
+}
+
+#[derive(Debug)]
+pub struct ProxyError {
+    message: String,
+}
+
+impl ProxyError {
+    fn new(message: &str) -> ProxyError {
+        ProxyError {
+            message: message.to_string(),
+        }
+    }
+}
+
+impl std::error::Error for ProxyError {}
+
+impl fmt::Display for ProxyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
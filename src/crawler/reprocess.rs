@@ -0,0 +1,190 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use reqwest::StatusCode;
+use rusqlite::{params, Connection};
+use url::Url;
+
+use super::pipeline;
+use crate::{
+    db::{blob::BodyReader, sink::DatabaseSink, DbError},
+    export::{ExportFilter, FILTER_CONDITION},
+    web::charset,
+    output::{
+        warc::{self, HttpRequest, WarcReader},
+        FetchedUrl, OutputRecord, OutputSink,
+    },
+};
+
+/// The number of responses reprocessed per transaction or output batch.
+const BATCH_SIZE: usize = 256;
+
+/// A response stored in an output database, without its headers
+struct StoredResponse {
+    id: i64,
+    url: String,
+    status: Option<u16>,
+    body_hash: Option<String>,
+    encoding: Option<String>,
+}
+
+/// Re-run link extraction and the analyzers over the responses stored in an output database, replacing their
+/// links, findings and indexed text. Findings of the disabled analyzers are dropped. Returns the number of responses
+/// reprocessed.
+pub fn reprocess_database(db: &mut Connection, filter: &ExportFilter, disabled_analyzers: &HashSet<String>) -> Result<usize, DbError> {
+    let responses: Vec<StoredResponse> = db
+        .prepare(&format!(
+            "SELECT responses.id, urls.url, responses.status, responses.body_hash, responses.encoding
+            FROM responses
+            JOIN urls ON urls.id = responses.url_id
+            JOIN hosts ON hosts.id = urls.host_id
+            WHERE {}
+            ORDER BY responses.id",
+            FILTER_CONDITION
+        ))?
+        .query_map(params![filter.target, filter.session], |row| {
+            Ok(StoredResponse {
+                id: row.get(0)?,
+                url: row.get(1)?,
+                status: row.get(2)?,
+                body_hash: row.get(3)?,
+                encoding: row.get(4)?,
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+
+    let mut bodies = BodyReader::default();
+
+    for batch in responses.chunks(BATCH_SIZE) {
+        let transaction = db.transaction()?;
+
+        for StoredResponse { id: response_id, url, status, body_hash, encoding } in batch {
+            let Ok(parsed_url) = Url::parse(url) else { continue; };
+            let status = status.and_then(|status| StatusCode::from_u16(status).ok()).unwrap_or_default();
+
+            let headers: Vec<(String, String)> = transaction
+                .prepare_cached("SELECT name, value FROM headers WHERE response_id = ?1 AND direction = 'response' ORDER BY id")?
+                .query_map([response_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<Result<_, _>>()?;
+
+            let body = match body_hash {
+                Some(hash) => bodies.read(&transaction, hash)?.unwrap_or_default(),
+                None => Vec::new(),
+            };
+
+            let mut processed = pipeline::process_response(status, &headers, &charset::decode_stored(&body, encoding.as_deref()));
+            processed.findings.retain(|finding| !disabled_analyzers.contains(&finding.kind));
+            DatabaseSink::replace_analysis(
+                &transaction,
+                *response_id,
+                url,
+                &processed.page,
+                &processed.links(&parsed_url),
+                &processed.findings,
+            )?;
+        }
+
+        transaction.commit()?;
+    }
+
+    Ok(responses.len())
+}
+
+/// Feed the responses archived in WARC files through the crawl pipeline into an output sink, as if they had just
+/// been fetched. Findings of the disabled analyzers are dropped. Returns the number of responses imported.
+pub fn reprocess_warc(
+    warc_paths: &[PathBuf],
+    mut sink: Box<dyn OutputSink>,
+    disabled_analyzers: &HashSet<String>,
+) -> Result<usize, Box<dyn Error>> {
+    let mut imported = 0;
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+
+    for warc_path in warc_paths {
+        let mut requests = archived_requests(warc_path)?;
+
+        for record in WarcReader::open(warc_path)? {
+            let record = record?;
+            if record.header("WARC-Type") != Some("response") {
+                continue;
+            }
+
+            let Some(url) = record.header("WARC-Target-URI").and_then(|url| Url::parse(url.trim_matches(['<', '>'])).ok()) else { continue; };
+            let Some(response) = warc::parse_http_response(record.block()) else { continue; };
+
+            let status = StatusCode::from_u16(response.status).unwrap_or_default();
+            let content_type = response
+                .headers
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+                .map(|(_, value)| value.as_str())
+                .unwrap_or_default();
+            let (text, encoding) = charset::decode(&response.body, content_type, &url);
+            let mut processed = pipeline::process_response(status, &response.headers, &text);
+            processed.findings.retain(|finding| !disabled_analyzers.contains(&finding.kind));
+            let host = url.host_str().unwrap_or_default().to_string();
+
+            let request = [record.header("WARC-Record-ID"), record.header("WARC-Concurrent-To")]
+                .into_iter()
+                .flatten()
+                .find_map(|id| requests.remove(id));
+            let (method, request_headers) = match request {
+                Some(request) => (request.method, request.headers),
+                None => ("GET".to_string(), Vec::new()),
+            };
+
+            batch.push(OutputRecord::Response(Box::new(FetchedUrl {
+                url: url.to_string(),
+                target: host.clone(),
+                host,
+                method,
+                status: response.status,
+                http_version: response.http_version,
+                request_headers,
+                fetched_at: record.header("WARC-Date").unwrap_or_default().to_string(),
+                wait_ms: 0,
+                duration_ms: 0,
+                encoding: (!response.body.is_empty()).then(|| encoding.name().to_string()),
+                headers: response.headers,
+                body: response.body,
+                truncated: record.header("WARC-Truncated").is_some(),
+                links: processed.links(&url),
+                page: processed.page,
+                findings: processed.findings,
+            })));
+
+            if batch.len() == BATCH_SIZE {
+                imported += batch.len();
+                sink.write_batch(&batch)?;
+                batch.clear();
+            }
+        }
+    }
+
+    imported += batch.len();
+    sink.write_batch(&batch)?;
+    sink.finish()?;
+
+    Ok(imported)
+}
+
+/// The requests archived in a WARC file, by the ID of the response record they belong to. A request names its
+/// response with WARC-Concurrent-To, or is named by it, depending on the tool that wrote the file.
+fn archived_requests(warc_path: &Path) -> Result<HashMap<String, HttpRequest>, Box<dyn Error>> {
+    let mut requests = HashMap::new();
+
+    for record in WarcReader::open(warc_path)? {
+        let record = record?;
+        if record.header("WARC-Type") != Some("request") {
+            continue;
+        }
+
+        let Some(request) = warc::parse_http_request(record.block()) else { continue; };
+        if let Some(id) = record.header("WARC-Concurrent-To").or(record.header("WARC-Record-ID")) {
+            requests.insert(id.to_string(), request);
+        }
+    }
+
+    Ok(requests)
+}
@@ -0,0 +1,320 @@
+use std::fmt;
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+
+use globset::{Glob, GlobMatcher};
+use ipnet::IpNet;
+use regex::Regex;
+use serde::Deserialize;
+use url::Url;
+
+/// Which URLs may be crawled, as include and exclude rules.
+///
+/// A URL is in scope if no exclude rule matches it and, when there are include rules, at least one of them does.
+#[derive(Debug, Default)]
+pub struct Scope {
+    include: Vec<Rule>,
+    exclude: Vec<Rule>,
+    debug: bool,
+}
+
+/// The outcome of checking a URL against the scope
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScopeDecision {
+    In,
+    Out(String), // Why the URL is out of scope
+}
+
+impl Scope {
+    /// Loads a scope file: TOML, JSON with the same layout, or a Burp Suite project options export.
+    pub fn load(path: &Path) -> Result<Scope, ScopeError> {
+        let contents = fs::read_to_string(path).map_err(|error| ScopeError::new(path, &error.to_string()))?;
+
+        let is_json = path.extension().is_some_and(|extension| extension == "json");
+        let scope = if !is_json {
+            let file: ScopeFile = toml::from_str(&contents).map_err(|error| ScopeError::new(path, &error.to_string()))?;
+            Self::from_file(file)
+        } else if let Ok(burp) = serde_json::from_str::<BurpOptions>(&contents) {
+            Self::from_burp(burp.target.scope)
+        } else {
+            let file: ScopeFile = serde_json::from_str(&contents).map_err(|error| ScopeError::new(path, &error.to_string()))?;
+            Self::from_file(file)
+        };
+
+        scope.map_err(|reason| ScopeError::new(path, &reason))
+    }
+
+    /// Makes the scope report every URL it rejects and why.
+    pub fn with_debug(mut self, debug: bool) -> Self {
+        self.debug = debug;
+        self
+    }
+
+    fn from_file(file: ScopeFile) -> Result<Scope, String> {
+        let compile = |kind: &str, rules: Vec<ScopeRule>| -> Result<Vec<Rule>, String> {
+            rules
+                .into_iter()
+                .enumerate()
+                .map(|(index, rule)| Rule::compile(format!("{} rule {}", kind, index + 1), rule))
+                .collect()
+        };
+
+        Ok(Scope {
+            include: compile("include", file.include)?,
+            exclude: compile("exclude", file.exclude)?,
+            debug: false,
+        })
+    }
+
+    fn from_burp(scope: BurpScope) -> Result<Scope, String> {
+        let compile = |kind: &str, rules: Vec<BurpRule>| -> Result<Vec<Rule>, String> {
+            rules
+                .into_iter()
+                .enumerate()
+                .filter(|(_, rule)| rule.enabled)
+                .map(|(index, rule)| Rule::from_burp(format!("Burp {} rule {}", kind, index + 1), rule))
+                .collect()
+        };
+
+        Ok(Scope {
+            include: compile("include", scope.include)?,
+            exclude: compile("exclude", scope.exclude)?,
+            debug: false,
+        })
+    }
+
+    /// Checks whether a URL is in scope.
+    pub fn check(&self, url: &Url) -> ScopeDecision {
+        if let Some(rule) = self.exclude.iter().find(|rule| rule.matches(url)) {
+            return ScopeDecision::Out(format!("matches {}", rule));
+        }
+
+        if !self.include.is_empty() && !self.include.iter().any(|rule| rule.matches(url)) {
+            return ScopeDecision::Out("matches no include rule".to_string());
+        }
+
+        ScopeDecision::In
+    }
+
+    /// Returns whether a URL is in scope, reporting why it is not if debugging is enabled.
+    pub fn allows(&self, url: &Url) -> bool {
+        match self.check(url) {
+            ScopeDecision::In => true,
+            ScopeDecision::Out(reason) => {
+                if self.debug {
+                    tracing::info!(%url, "Out of scope: {}", reason);
+                } else {
+                    tracing::debug!(%url, "Out of scope: {}", reason);
+                }
+                false
+            }
+        }
+    }
+}
+
+/// A rule matches a URL if all of its conditions do
+#[derive(Debug)]
+struct Rule {
+    description: String,
+    host: Option<Pattern>,
+    port: Option<Pattern>,
+    scheme: Option<String>,
+    path: Option<Regex>,
+    url: Option<Regex>,
+    cidr: Option<IpNet>,
+}
+
+impl Rule {
+    fn compile(description: String, rule: ScopeRule) -> Result<Rule, String> {
+        let host = match rule.host {
+            Some(host) => Some(Pattern::Glob(
+                Glob::new(&host.to_ascii_lowercase())
+                    .map_err(|error| format!("{}: {}", description, error))?
+                    .compile_matcher(),
+            )),
+            None => None,
+        };
+        let url = match rule.url {
+            Some(url) => Some(Regex::new(&url).map_err(|error| format!("{}: {}", description, error))?),
+            None => None,
+        };
+        let cidr = match rule.cidr {
+            Some(cidr) => Some(cidr.parse().map_err(|error| format!("{}: {}", description, error))?),
+            None => None,
+        };
+
+        Ok(Rule {
+            description,
+            host,
+            port: rule.port.map(|port| Pattern::Exact(port.to_string())),
+            scheme: rule.scheme.map(|scheme| scheme.to_ascii_lowercase()),
+            path: None,
+            url,
+            cidr,
+        })
+    }
+
+    fn from_burp(description: String, rule: BurpRule) -> Result<Rule, String> {
+        let regex = |pattern: Option<String>| -> Result<Option<Regex>, String> {
+            match pattern.filter(|pattern| !pattern.is_empty()) {
+                Some(pattern) => Regex::new(&pattern).map(Some).map_err(|error| format!("{}: {}", description, error)),
+                None => Ok(None),
+            }
+        };
+
+        // Without advanced mode Burp scopes are URL prefixes
+        let url = rule.prefix.map(|prefix| format!("^{}", regex::escape(&prefix)));
+
+        Ok(Rule {
+            host: regex(rule.host)?.map(Pattern::Regex),
+            port: regex(rule.port)?.map(Pattern::Regex),
+            scheme: rule.protocol.filter(|protocol| protocol != "any"),
+            path: regex(rule.file)?,
+            url: regex(url)?,
+            cidr: None,
+            description,
+        })
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        let host = url.host_str().unwrap_or_default().trim_matches(['[', ']']).to_ascii_lowercase();
+        let port = url.port_or_known_default().map(|port| port.to_string()).unwrap_or_default();
+
+        self.host.as_ref().is_none_or(|pattern| pattern.matches(&host))
+            && self.port.as_ref().is_none_or(|pattern| pattern.matches(&port))
+            && self.scheme.as_ref().is_none_or(|scheme| scheme == url.scheme())
+            && self.path.as_ref().is_none_or(|path| path.is_match(url.path()))
+            && self.url.as_ref().is_none_or(|regex| regex.is_match(url.as_str()))
+            // Domain names are not resolved, so CIDR conditions only match IP hosts
+            && self.cidr.as_ref().is_none_or(|cidr| host.parse::<IpAddr>().is_ok_and(|ip| cidr.contains(&ip)))
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut conditions = Vec::new();
+        if let Some(host) = &self.host {
+            conditions.push(format!("host {}", host));
+        }
+        if let Some(port) = &self.port {
+            conditions.push(format!("port {}", port));
+        }
+        if let Some(scheme) = &self.scheme {
+            conditions.push(format!("scheme {}", scheme));
+        }
+        if let Some(path) = &self.path {
+            conditions.push(format!("path {}", path));
+        }
+        if let Some(url) = &self.url {
+            conditions.push(format!("url {}", url));
+        }
+        if let Some(cidr) = &self.cidr {
+            conditions.push(format!("cidr {}", cidr));
+        }
+
+        write!(f, "{} ({})", self.description, conditions.join(", "))
+    }
+}
+
+#[derive(Debug)]
+enum Pattern {
+    Exact(String),
+    Glob(GlobMatcher),
+    Regex(Regex),
+}
+
+impl Pattern {
+    fn matches(&self, value: &str) -> bool {
+        match self {
+            Pattern::Exact(exact) => exact == value,
+            Pattern::Glob(glob) => glob.is_match(value),
+            Pattern::Regex(regex) => regex.is_match(value),
+        }
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pattern::Exact(exact) => write!(f, "{}", exact),
+            Pattern::Glob(glob) => write!(f, "{}", glob.glob()),
+            Pattern::Regex(regex) => write!(f, "{}", regex),
+        }
+    }
+}
+
+/// The layout of TOML and JSON scope files
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScopeFile {
+    #[serde(default)]
+    include: Vec<ScopeRule>,
+    #[serde(default)]
+    exclude: Vec<ScopeRule>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScopeRule {
+    host: Option<String>,   // A glob on the host name
+    url: Option<String>,    // A regex on the whole URL
+    port: Option<u16>,
+    scheme: Option<String>,
+    cidr: Option<String>,
+}
+
+/// The parts of a Burp Suite project options export that define the target scope
+#[derive(Deserialize)]
+struct BurpOptions {
+    target: BurpTarget,
+}
+
+#[derive(Deserialize)]
+struct BurpTarget {
+    scope: BurpScope,
+}
+
+#[derive(Deserialize)]
+struct BurpScope {
+    #[serde(default)]
+    include: Vec<BurpRule>,
+    #[serde(default)]
+    exclude: Vec<BurpRule>,
+}
+
+#[derive(Deserialize)]
+struct BurpRule {
+    #[serde(default = "enabled")]
+    enabled: bool,
+    host: Option<String>,
+    port: Option<String>,
+    protocol: Option<String>,
+    file: Option<String>,
+    prefix: Option<String>,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+#[derive(Debug)]
+pub struct ScopeError {
+    message: String,
+}
+
+impl ScopeError {
+    fn new(path: &Path, reason: &str) -> ScopeError {
+        ScopeError {
+            message: format!("Invalid scope file {}: {}", path.display(), reason),
+        }
+    }
+}
+
+impl std::error::Error for ScopeError {}
+
+impl fmt::Display for ScopeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
@@ -0,0 +1,219 @@
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::sync::watch;
+
+use super::metrics::Histogram;
+
+/// How many recent hosts and errors are kept
+const RECENT_CAPACITY: usize = 10;
+
+/// Live statistics of a crawl, and the controls to pause it, to skip targets and to stop it.
+///
+/// Shared by the crawler tasks, which update it, and the dashboard, which shows it.
+#[derive(Debug)]
+pub struct CrawlStats {
+    pub started: Instant,
+    pub requests: AtomicU64,
+    pub in_flight: AtomicUsize,
+    pub errors: AtomicU64,
+    /// Requests sent again after a transient failure
+    pub retries: AtomicU64,
+    pub downloaded_bytes: AtomicU64,
+    statuses: Mutex<BTreeMap<u16, u64>>,
+    errors_by_kind: Mutex<BTreeMap<&'static str, u64>>,
+    latencies: Mutex<BTreeMap<String, Histogram>>,
+    db_writes: Mutex<Histogram>,
+    targets: Mutex<BTreeMap<String, Arc<TargetStats>>>,
+    recent_hosts: Mutex<VecDeque<String>>,
+    recent_errors: Mutex<VecDeque<String>>,
+    skipped: Mutex<HashSet<String>>,
+    paused: watch::Sender<bool>,
+    stopping: AtomicBool,
+}
+
+/// Statistics of a target being crawled, keyed by its origin
+#[derive(Debug, Default)]
+pub struct TargetStats {
+    pub queued: AtomicUsize,
+    pub in_flight: AtomicUsize,
+    pub done: AtomicU64,
+}
+
+impl CrawlStats {
+    pub fn new() -> CrawlStats {
+        CrawlStats {
+            started: Instant::now(),
+            requests: AtomicU64::new(0),
+            in_flight: AtomicUsize::new(0),
+            errors: AtomicU64::new(0),
+            retries: AtomicU64::new(0),
+            downloaded_bytes: AtomicU64::new(0),
+            statuses: Mutex::default(),
+            errors_by_kind: Mutex::default(),
+            latencies: Mutex::default(),
+            db_writes: Mutex::default(),
+            targets: Mutex::default(),
+            recent_hosts: Mutex::default(),
+            recent_errors: Mutex::default(),
+            skipped: Mutex::default(),
+            paused: watch::Sender::new(false),
+            stopping: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns the statistics of a target, registering it as active.
+    pub fn target(&self, origin: &str) -> Arc<TargetStats> {
+        let mut targets = self.targets.lock().unwrap();
+        Arc::clone(targets.entry(origin.to_string()).or_default())
+    }
+
+    /// Removes a target from the active ones once it has been crawled.
+    pub fn finish_target(&self, origin: &str) {
+        self.targets.lock().unwrap().remove(origin);
+        self.skipped.lock().unwrap().remove(origin);
+    }
+
+    /// Returns the active targets and their statistics, ordered by origin.
+    pub fn active_targets(&self) -> Vec<(String, Arc<TargetStats>)> {
+        self.targets.lock().unwrap().iter().map(|(origin, stats)| (origin.clone(), Arc::clone(stats))).collect()
+    }
+
+    /// How many requests are queued across all targets.
+    pub fn frontier(&self) -> usize {
+        self.targets.lock().unwrap().values().map(|target| target.queued.load(Ordering::Relaxed)).sum()
+    }
+
+    /// Counts a request as waiting for its turn until the returned guard is dropped.
+    pub fn queue(&self, target: &Arc<TargetStats>) -> Queued {
+        target.queued.fetch_add(1, Ordering::Relaxed);
+        Queued(Arc::clone(target))
+    }
+
+    /// Counts a request as in flight until the returned guard is dropped.
+    pub fn request(self: &Arc<Self>, target: &Arc<TargetStats>) -> InFlight {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        target.in_flight.fetch_add(1, Ordering::Relaxed);
+        InFlight(Arc::clone(self), Arc::clone(target))
+    }
+
+    pub fn status(&self, status: u16) {
+        *self.statuses.lock().unwrap().entry(status).or_default() += 1;
+    }
+
+    /// Returns how many responses had each status code.
+    pub fn statuses(&self) -> Vec<(u16, u64)> {
+        self.statuses.lock().unwrap().iter().map(|(&status, &count)| (status, count)).collect()
+    }
+
+    /// Counts an error of the given kind, such as `timeout` or `connect`.
+    pub fn error(&self, kind: &'static str, error: String) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+        *self.errors_by_kind.lock().unwrap().entry(kind).or_default() += 1;
+        push_recent(&self.recent_errors, error);
+    }
+
+    pub fn errors_by_kind(&self) -> Vec<(&'static str, u64)> {
+        self.errors_by_kind.lock().unwrap().iter().map(|(&kind, &count)| (kind, count)).collect()
+    }
+
+    /// Records how long a host took to send the response headers.
+    pub fn latency(&self, host: &str, duration: Duration) {
+        self.latencies.lock().unwrap().entry(host.to_string()).or_default().observe(duration);
+    }
+
+    pub fn latencies(&self) -> Vec<(String, Histogram)> {
+        self.latencies.lock().unwrap().iter().map(|(host, histogram)| (host.clone(), histogram.clone())).collect()
+    }
+
+    /// Records how long a batch of records took to write to the database.
+    pub fn db_write(&self, duration: Duration) {
+        self.db_writes.lock().unwrap().observe(duration);
+    }
+
+    pub fn db_writes(&self) -> Histogram {
+        self.db_writes.lock().unwrap().clone()
+    }
+
+    pub fn discovered(&self, host: String) {
+        push_recent(&self.recent_hosts, host);
+    }
+
+    pub fn recent_hosts(&self) -> Vec<String> {
+        self.recent_hosts.lock().unwrap().iter().cloned().collect()
+    }
+
+    pub fn recent_errors(&self) -> Vec<String> {
+        self.recent_errors.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Stops crawling a target: queued requests are dropped and no new links are followed.
+    pub fn skip(&self, origin: &str) {
+        self.skipped.lock().unwrap().insert(origin.to_string());
+    }
+
+    pub fn is_skipped(&self, origin: &str) -> bool {
+        self.is_stopping() || self.skipped.lock().unwrap().contains(origin)
+    }
+
+    /// Stops the whole crawl as if every target was skipped. What is in flight is finished and written out.
+    pub fn stop(&self) {
+        self.stopping.store(true, Ordering::Relaxed);
+        self.set_paused(false);
+    }
+
+    pub fn is_stopping(&self) -> bool {
+        self.stopping.load(Ordering::Relaxed)
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.send_replace(paused);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.paused.borrow()
+    }
+
+    /// Waits until the crawl is not paused.
+    pub async fn wait_while_paused(&self) {
+        let mut paused = self.paused.subscribe();
+        let _ = paused.wait_for(|paused| !paused).await;
+    }
+}
+
+impl Default for CrawlStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn push_recent(recent: &Mutex<VecDeque<String>>, entry: String) {
+    let mut recent = recent.lock().unwrap();
+    if recent.len() == RECENT_CAPACITY {
+        recent.pop_front();
+    }
+    recent.push_back(entry);
+}
+
+/// A request waiting for its turn
+pub struct Queued(Arc<TargetStats>);
+
+impl Drop for Queued {
+    fn drop(&mut self) {
+        self.0.queued.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// A request in flight
+pub struct InFlight(Arc<CrawlStats>, Arc<TargetStats>);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::Relaxed);
+        self.1.in_flight.fetch_sub(1, Ordering::Relaxed);
+        self.1.done.fetch_add(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::{hash_map::Entry, HashMap};
+
+use rusqlite::{params, Connection, OptionalExtension};
+use sha2::{Digest, Sha256};
+use zstd::bulk::{Compressor, Decompressor};
+use zstd::zstd_safe;
+
+use super::DbError;
+
+/// The zstd compression level used for stored bodies.
+const COMPRESSION_LEVEL: i32 = 9;
+
+/// The number of bodies sampled before a dictionary is trained.
+const DICTIONARY_SAMPLES: usize = 256;
+
+/// The maximum size of a trained dictionary.
+const MAX_DICTIONARY_SIZE: usize = 112 * 1024;
+
+/// Returns the content hash under which a body is stored.
+pub fn content_hash(body: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(body))
+}
+
+/// Stores response bodies once per content hash, compressed with zstd.
+pub struct BodyStore {
+    max_size: usize,
+    compressor: Compressor<'static>,
+    dictionary_id: Option<i64>,
+    samples: Option<Vec<Vec<u8>>>,
+}
+
+impl BodyStore {
+    /// Creates a body store for an output database, continuing with the dictionary of a previous run if there is one.
+    pub fn new(db: &Connection, max_size: usize, train_dictionary: bool) -> Result<Self, DbError> {
+        let dictionary: Option<(i64, Vec<u8>)> = db
+            .query_row("SELECT id, data FROM dictionaries ORDER BY id DESC LIMIT 1", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .optional()?;
+
+        let (compressor, dictionary_id) = match dictionary {
+            Some((id, data)) => (Compressor::with_dictionary(COMPRESSION_LEVEL, &data)?, Some(id)),
+            None => (Compressor::new(COMPRESSION_LEVEL)?, None),
+        };
+
+        Ok(BodyStore {
+            max_size,
+            compressor,
+            dictionary_id,
+            samples: (train_dictionary && dictionary_id.is_none()).then(Vec::new),
+        })
+    }
+
+    /// Stores a body unless it is larger than the configured maximum, returning its content hash.
+    pub fn store(&mut self, db: &Connection, body: &[u8]) -> Result<Option<String>, DbError> {
+        if body.len() > self.max_size {
+            return Ok(None);
+        }
+
+        let hash = content_hash(body);
+        let exists: bool = db
+            .prepare_cached("SELECT EXISTS (SELECT 1 FROM blobs WHERE hash = ?1)")?
+            .query_row([&hash], |row| row.get(0))?;
+
+        if !exists {
+            self.sample(db, body)?;
+
+            db.prepare_cached("INSERT INTO blobs (hash, size, dictionary_id, data) VALUES (?1, ?2, ?3, ?4)")?
+                .execute(params![hash, body.len(), self.dictionary_id, self.compressor.compress(body)?])?;
+        }
+
+        Ok(Some(hash))
+    }
+
+    /// Collects a training sample, training and switching to a dictionary once enough have been seen.
+    fn sample(&mut self, db: &Connection, body: &[u8]) -> Result<(), DbError> {
+        let Some(samples) = &mut self.samples else { return Ok(()); };
+
+        samples.push(body.to_vec());
+        if samples.len() < DICTIONARY_SAMPLES {
+            return Ok(());
+        }
+
+        let samples = self.samples.take().unwrap_or_default();
+        match zstd::dict::from_samples(&samples, MAX_DICTIONARY_SIZE) {
+            Ok(dictionary) => {
+                db.execute("INSERT INTO dictionaries (data) VALUES (?1)", [&dictionary])?;
+                self.dictionary_id = Some(db.last_insert_rowid());
+                self.compressor = Compressor::with_dictionary(COMPRESSION_LEVEL, &dictionary)?;
+            }
+            // Too little or too uniform data to train on, carry on without a dictionary
+            Err(error) => tracing::warn!("Failed to train compression dictionary: {}", error),
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads and decompresses stored bodies.
+#[derive(Default)]
+pub struct BodyReader {
+    dictionaries: HashMap<i64, Vec<u8>>,
+}
+
+impl BodyReader {
+    /// Returns the body stored under a content hash, if there is one.
+    pub fn read(&mut self, db: &Connection, hash: &str) -> Result<Option<Vec<u8>>, DbError> {
+        let blob: Option<(usize, Option<i64>, Vec<u8>)> = db
+            .prepare_cached("SELECT size, dictionary_id, data FROM blobs WHERE hash = ?1")?
+            .query_row([hash], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .optional()?;
+
+        let Some((size, dictionary_id, data)) = blob else { return Ok(None); };
+
+        let mut decompressor = match dictionary_id {
+            Some(id) => Decompressor::with_dictionary(self.dictionary(db, id)?)?,
+            None => Decompressor::new()?,
+        };
+
+        Ok(Some(decompressor.decompress(&data, size)?))
+    }
+
+    /// Decompresses the data column of a stored body selected on its own, as by an ad-hoc query. Returns None if
+    /// the data is not a body compressed by a body store.
+    pub fn decompress(&mut self, db: &Connection, data: &[u8]) -> Result<Option<Vec<u8>>, DbError> {
+        let Ok(Some(size)) = zstd_safe::get_frame_content_size(data) else { return Ok(None); };
+
+        let mut decompressor = match zstd_safe::get_dict_id_from_frame(data) {
+            Some(frame_dictionary) => {
+                let ids: Vec<i64> = db
+                    .prepare_cached("SELECT id FROM dictionaries")?
+                    .query_map([], |row| row.get(0))?
+                    .collect::<Result<_, _>>()?;
+
+                let mut matching = None;
+                for id in ids {
+                    if zstd_safe::get_dict_id_from_dict(self.dictionary(db, id)?) == Some(frame_dictionary) {
+                        matching = Some(id);
+                        break;
+                    }
+                }
+
+                let Some(id) = matching else { return Ok(None); };
+                Decompressor::with_dictionary(self.dictionary(db, id)?)?
+            }
+            None => Decompressor::new()?,
+        };
+
+        Ok(decompressor.decompress(data, size as usize).ok())
+    }
+
+    fn dictionary(&mut self, db: &Connection, id: i64) -> Result<&[u8], DbError> {
+        let dictionary = match self.dictionaries.entry(id) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                entry.insert(db.query_row("SELECT data FROM dictionaries WHERE id = ?1", [id], |row| row.get(0))?)
+            }
+        };
+
+        Ok(dictionary)
+    }
+}
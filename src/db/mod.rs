@@ -0,0 +1,78 @@
+pub mod blob;
+pub mod schema;
+pub mod search;
+pub mod sink;
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use rusqlite::{Connection, OpenFlags};
+
+/// Opens an output database, upgrading its schema to the latest version if necessary.
+pub fn open(db_path: &Path) -> Result<Connection, DbError> {
+    let mut db = Connection::open(db_path)?;
+
+    db.pragma_update(None, "foreign_keys", true)?;
+    schema::migrate(&mut db)?;
+
+    Ok(db)
+}
+
+/// Opens an existing output database for reading only. A database written by an older version is upgraded first.
+pub fn open_read_only(db_path: &Path) -> Result<Connection, DbError> {
+    if !db_path.is_file() {
+        return Err(DbError::NotFound(db_path.to_path_buf()));
+    }
+
+    let db = Connection::open_with_flags(db_path, OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)?;
+    match schema::stored_version(&db)? {
+        None => Err(DbError::NotAnOutputDatabase(db_path.to_path_buf())),
+        Some(version) if version < schema::latest_version() => {
+            drop(db);
+            open(db_path)?.close().map_err(|(_, error)| error)?;
+            Ok(Connection::open_with_flags(db_path, OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)?)
+        }
+        Some(version) if version > schema::latest_version() => Err(DbError::UnsupportedSchemaVersion(version)),
+        Some(_) => Ok(db),
+    }
+}
+
+#[derive(Debug)]
+pub enum DbError {
+    Sqlite(rusqlite::Error),
+    Compression(std::io::Error),
+    UnsupportedSchemaVersion(u32),
+    NotFound(PathBuf),
+    NotAnOutputDatabase(PathBuf),
+}
+
+impl std::error::Error for DbError {}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Sqlite(error) => write!(f, "{}", error),
+            Self::Compression(error) => write!(f, "Failed to (de)compress body: {}", error),
+            Self::UnsupportedSchemaVersion(version) => write!(
+                f,
+                "Database schema version {} is newer than the supported version {}!",
+                version,
+                schema::latest_version()
+            ),
+            Self::NotFound(path) => write!(f, "No database at {}", path.display()),
+            Self::NotAnOutputDatabase(path) => write!(f, "{} is not an output database", path.display()),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for DbError {
+    fn from(error: rusqlite::Error) -> Self {
+        DbError::Sqlite(error)
+    }
+}
+
+impl From<std::io::Error> for DbError {
+    fn from(error: std::io::Error) -> Self {
+        DbError::Compression(error)
+    }
+}
@@ -0,0 +1,354 @@
+use rusqlite::{params, Connection, Transaction};
+use scraper::Html;
+
+use super::{
+    blob::{content_hash, BodyReader, BodyStore},
+    DbError,
+};
+use crate::web::html;
+
+type Migration = fn(&Transaction) -> Result<(), DbError>;
+
+/// The schema migrations, in order. Migration `i` upgrades a database from version `i` to `i + 1`.
+const MIGRATIONS: &[Migration] = &[
+    create_normalized_schema,
+    move_bodies_to_blobs,
+    create_page_text_index,
+    add_sessions_and_request_details,
+    create_services,
+    add_session_settings,
+    create_certificates,
+    add_host_source,
+    create_fetch_errors,
+    add_response_method_and_truncation,
+    add_response_encoding,
+    add_certificate_port,
+];
+
+/// Returns the schema version this build of the crawler writes.
+pub fn latest_version() -> u32 {
+    MIGRATIONS.len() as u32
+}
+
+/// Returns the schema version of a database, 0 if it has never been migrated.
+pub fn current_version(db: &Connection) -> Result<u32, rusqlite::Error> {
+    db.execute_batch(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY,
+            applied_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP)",
+    )?;
+
+    db.query_row("SELECT COALESCE(MAX(version), 0) FROM schema_version", [], |row| row.get(0))
+}
+
+/// Returns the schema version of a database without creating anything, None if it is not an output database.
+pub fn stored_version(db: &Connection) -> Result<Option<u32>, rusqlite::Error> {
+    if table_exists(db, "schema_version")? {
+        db.query_row("SELECT COALESCE(MAX(version), 0) FROM schema_version", [], |row| row.get(0)).map(Some)
+    } else if table_exists(db, "targets")? {
+        // Written before the schema was versioned
+        Ok(Some(0))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Brings a database up to the latest schema version, applying each missing migration in its own transaction.
+pub fn migrate(db: &mut Connection) -> Result<(), DbError> {
+    let version = current_version(db)?;
+
+    if version > latest_version() {
+        return Err(DbError::UnsupportedSchemaVersion(version));
+    }
+
+    for (from_version, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let transaction = db.transaction()?;
+        migration(&transaction)?;
+        transaction.execute("INSERT INTO schema_version (version) VALUES (?1)", [from_version as u32 + 1])?;
+        transaction.commit()?;
+    }
+
+    Ok(())
+}
+
+/// Returns whether a table exists in the database.
+fn table_exists(db: &Connection, table: &str) -> Result<bool, rusqlite::Error> {
+    db.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1)",
+        [table],
+        |row| row.get(0),
+    )
+}
+
+/// Version 1: hosts, urls, responses, headers, links and findings related by foreign keys.
+///
+/// Databases written before versioning (`targets` and a flat `urls` table) are carried over.
+fn create_normalized_schema(db: &Transaction) -> Result<(), DbError> {
+    let legacy = table_exists(db, "targets")?;
+    if legacy {
+        db.execute_batch("ALTER TABLE urls RENAME TO legacy_urls")?;
+    }
+
+    db.execute_batch(
+        "CREATE TABLE hosts (
+            id INTEGER PRIMARY KEY,
+            host TEXT NOT NULL UNIQUE);
+
+        CREATE TABLE urls (
+            id INTEGER PRIMARY KEY,
+            host_id INTEGER NOT NULL REFERENCES hosts (id) ON DELETE CASCADE,
+            url TEXT NOT NULL UNIQUE);
+
+        CREATE TABLE responses (
+            id INTEGER PRIMARY KEY,
+            url_id INTEGER NOT NULL REFERENCES urls (id) ON DELETE CASCADE,
+            status INTEGER,
+            fetched_at TEXT,
+            duration_ms INTEGER,
+            body BLOB);
+
+        CREATE TABLE headers (
+            id INTEGER PRIMARY KEY,
+            response_id INTEGER NOT NULL REFERENCES responses (id) ON DELETE CASCADE,
+            name TEXT NOT NULL,
+            value TEXT NOT NULL);
+
+        CREATE TABLE links (
+            id INTEGER PRIMARY KEY,
+            response_id INTEGER NOT NULL REFERENCES responses (id) ON DELETE CASCADE,
+            url TEXT NOT NULL);
+
+        CREATE TABLE findings (
+            id INTEGER PRIMARY KEY,
+            response_id INTEGER NOT NULL REFERENCES responses (id) ON DELETE CASCADE,
+            kind TEXT NOT NULL,
+            detail TEXT);
+
+        CREATE INDEX urls_host_id ON urls (host_id);
+        CREATE INDEX responses_url_id ON responses (url_id);
+        CREATE INDEX responses_status ON responses (status);
+        CREATE INDEX headers_response_id ON headers (response_id);
+        CREATE INDEX headers_name ON headers (name);
+        CREATE INDEX links_response_id ON links (response_id);
+        CREATE INDEX links_url ON links (url);
+        CREATE INDEX findings_response_id ON findings (response_id);
+        CREATE INDEX findings_kind ON findings (kind);",
+    )?;
+
+    if legacy {
+        db.execute_batch(
+            "INSERT OR IGNORE INTO hosts (host) SELECT host FROM targets WHERE host IS NOT NULL;
+            INSERT OR IGNORE INTO hosts (host) SELECT target FROM legacy_urls;
+
+            INSERT OR IGNORE INTO urls (host_id, url)
+                SELECT hosts.id, legacy_urls.url FROM legacy_urls JOIN hosts ON hosts.host = legacy_urls.target;
+
+            INSERT INTO responses (url_id, status, body)
+                SELECT urls.id, legacy_urls.response_code, legacy_urls.response_body
+                FROM legacy_urls JOIN urls ON urls.url = legacy_urls.url;
+
+            DROP TABLE legacy_urls;
+            DROP TABLE targets;",
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Version 2: bodies are stored once per content hash in a compressed blob table.
+fn move_bodies_to_blobs(db: &Transaction) -> Result<(), DbError> {
+    db.execute_batch(
+        "CREATE TABLE dictionaries (
+            id INTEGER PRIMARY KEY,
+            data BLOB NOT NULL);
+
+        CREATE TABLE blobs (
+            hash TEXT PRIMARY KEY,
+            size INTEGER NOT NULL,
+            dictionary_id INTEGER REFERENCES dictionaries (id),
+            data BLOB NOT NULL);
+
+        ALTER TABLE responses ADD COLUMN body_hash TEXT REFERENCES blobs (hash);
+        CREATE INDEX responses_body_hash ON responses (body_hash);",
+    )?;
+
+    let mut bodies = BodyStore::new(db, usize::MAX, false)?;
+    let mut select = db.prepare("SELECT id, CAST(body AS BLOB) FROM responses WHERE body IS NOT NULL")?;
+    let mut rows = select.query([])?;
+
+    while let Some(row) = rows.next()? {
+        let id: i64 = row.get(0)?;
+        let body: Vec<u8> = row.get(1)?;
+
+        bodies.store(db, &body)?;
+        db.execute("UPDATE responses SET body_hash = ?1 WHERE id = ?2", params![content_hash(&body), id])?;
+    }
+
+    drop(rows);
+    drop(select);
+
+    db.execute_batch("ALTER TABLE responses DROP COLUMN body")?;
+
+    Ok(())
+}
+
+/// Version 3: a full-text index over the URL, title and text of every stored page.
+fn create_page_text_index(db: &Transaction) -> Result<(), DbError> {
+    db.execute_batch(
+        "CREATE VIRTUAL TABLE page_text USING fts5 (
+            url,
+            title,
+            text,
+            tokenize = 'unicode61 remove_diacritics 2')",
+    )?;
+
+    let mut bodies = BodyReader::default();
+    let mut select = db.prepare(
+        "SELECT responses.id, urls.url, responses.body_hash FROM responses
+        JOIN urls ON urls.id = responses.url_id
+        WHERE responses.body_hash IS NOT NULL",
+    )?;
+    let mut rows = select.query([])?;
+
+    while let Some(row) = rows.next()? {
+        let id: i64 = row.get(0)?;
+        let url: String = row.get(1)?;
+        let body_hash: String = row.get(2)?;
+
+        let Some(body) = bodies.read(db, &body_hash)? else { continue; };
+        let page = html::page_text(&Html::parse_document(&String::from_utf8_lossy(&body)));
+
+        db.execute(
+            "INSERT INTO page_text (rowid, url, title, text) VALUES (?1, ?2, ?3, ?4)",
+            params![id, url, page.title, page.text],
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Version 4: crawl sessions, plus the request headers, HTTP version and time to first byte of each exchange.
+fn add_sessions_and_request_details(db: &Transaction) -> Result<(), DbError> {
+    db.execute_batch(
+        "CREATE TABLE sessions (
+            id INTEGER PRIMARY KEY,
+            started_at TEXT NOT NULL,
+            finished_at TEXT);
+
+        ALTER TABLE responses ADD COLUMN session_id INTEGER REFERENCES sessions (id);
+        ALTER TABLE responses ADD COLUMN http_version TEXT;
+        ALTER TABLE responses ADD COLUMN wait_ms INTEGER;
+        ALTER TABLE headers ADD COLUMN direction TEXT NOT NULL DEFAULT 'response';
+
+        CREATE INDEX responses_session_id ON responses (session_id);",
+    )?;
+
+    Ok(())
+}
+
+/// Version 5: the services found by probing each host, one row per scheme and port tried.
+fn create_services(db: &Transaction) -> Result<(), DbError> {
+    db.execute_batch(
+        "CREATE TABLE services (
+            id INTEGER PRIMARY KEY,
+            host_id INTEGER NOT NULL REFERENCES hosts (id),
+            session_id INTEGER REFERENCES sessions (id),
+            scheme TEXT NOT NULL,
+            port INTEGER NOT NULL,
+            probed_at TEXT NOT NULL,
+            status INTEGER,
+            error TEXT);
+
+        CREATE INDEX services_host_id ON services (host_id);",
+    )?;
+
+    Ok(())
+}
+
+/// Version 6: the settings and targets each session was started with, so that it can be resumed.
+fn add_session_settings(db: &Transaction) -> Result<(), DbError> {
+    db.execute_batch(
+        "ALTER TABLE sessions ADD COLUMN settings TEXT;
+        ALTER TABLE sessions ADD COLUMN targets TEXT;",
+    )?;
+
+    Ok(())
+}
+
+/// Version 7: the certificate chains hosts presented, one row per certificate, the host's own at position 0.
+fn create_certificates(db: &Transaction) -> Result<(), DbError> {
+    db.execute_batch(
+        "CREATE TABLE certificates (
+            id INTEGER PRIMARY KEY,
+            host_id INTEGER NOT NULL REFERENCES hosts (id),
+            session_id INTEGER REFERENCES sessions (id),
+            seen_at TEXT NOT NULL,
+            position INTEGER NOT NULL,
+            subject TEXT NOT NULL,
+            issuer TEXT NOT NULL,
+            serial TEXT NOT NULL,
+            not_before TEXT NOT NULL,
+            not_after TEXT NOT NULL,
+            subject_alt_names TEXT NOT NULL,
+            sha256 TEXT NOT NULL,
+            der BLOB NOT NULL,
+            error TEXT);
+
+        CREATE INDEX certificates_host_id ON certificates (host_id);",
+    )?;
+
+    Ok(())
+}
+
+/// Version 8: how each host was discovered: given as a target, probed, linked, or named by a certificate.
+fn add_host_source(db: &Transaction) -> Result<(), DbError> {
+    db.execute_batch("ALTER TABLE hosts ADD COLUMN source TEXT;")?;
+
+    Ok(())
+}
+
+/// Version 9: the URLs that could not be fetched, with how the failure was classified and how often it was tried.
+fn create_fetch_errors(db: &Transaction) -> Result<(), DbError> {
+    db.execute_batch(
+        "CREATE TABLE fetch_errors (
+            id INTEGER PRIMARY KEY,
+            url_id INTEGER NOT NULL REFERENCES urls (id) ON DELETE CASCADE,
+            session_id INTEGER REFERENCES sessions (id),
+            fetched_at TEXT NOT NULL,
+            attempts INTEGER NOT NULL,
+            kind TEXT NOT NULL,
+            detail TEXT NOT NULL);
+
+        CREATE INDEX fetch_errors_url_id ON fetch_errors (url_id);
+        CREATE INDEX fetch_errors_kind ON fetch_errors (kind);",
+    )?;
+
+    Ok(())
+}
+
+/// Version 10: the method of each request, as large binary resources are only asked for with HEAD, and whether the
+/// body was cut short at the maximum body size.
+fn add_response_method_and_truncation(db: &Transaction) -> Result<(), DbError> {
+    db.execute_batch(
+        "ALTER TABLE responses ADD COLUMN method TEXT NOT NULL DEFAULT 'GET';
+        ALTER TABLE responses ADD COLUMN truncated INTEGER NOT NULL DEFAULT 0;",
+    )?;
+
+    Ok(())
+}
+
+/// Version 11: the encoding each body was detected to be in. Bodies are now stored as received rather than as UTF-8,
+/// so those of older versions have none.
+fn add_response_encoding(db: &Transaction) -> Result<(), DbError> {
+    db.execute_batch("ALTER TABLE responses ADD COLUMN encoding TEXT;")?;
+
+    Ok(())
+}
+
+/// Version 12: the port of the service each certificate chain was presented by, as the ports of a host may present
+/// different ones. Chains recorded by older versions have none.
+fn add_certificate_port(db: &Transaction) -> Result<(), DbError> {
+    db.execute_batch("ALTER TABLE certificates ADD COLUMN port INTEGER;")?;
+
+    Ok(())
+}
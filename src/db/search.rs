@@ -0,0 +1,35 @@
+use rusqlite::{params, Connection};
+
+use super::DbError;
+
+/// A page matching a full-text query
+#[derive(Debug)]
+pub struct SearchHit {
+    pub url: String,
+    pub title: Option<String>,
+    pub snippet: String,
+}
+
+/// Runs an FTS5 query over the stored pages, best matches first.
+///
+/// Supports the full FTS5 query syntax: phrases, prefixes, AND/OR/NOT and column filters such as `title:login`.
+pub fn search(db: &Connection, query: &str, limit: usize) -> Result<Vec<SearchHit>, DbError> {
+    let mut select = db.prepare(
+        "SELECT url, title, snippet(page_text, 2, '[', ']', '...', 16) FROM page_text
+        WHERE page_text MATCH ?1
+        ORDER BY rank
+        LIMIT ?2",
+    )?;
+
+    let hits = select
+        .query_map(params![query, limit], |row| {
+            Ok(SearchHit {
+                url: row.get(0)?,
+                title: row.get(1)?,
+                snippet: row.get(2)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(hits)
+}
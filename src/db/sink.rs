@@ -0,0 +1,220 @@
+use std::error::Error;
+use std::path::Path;
+
+use chrono::{SecondsFormat, Utc};
+use rusqlite::{params, Connection, Transaction};
+
+use super::{blob::BodyStore, DbError};
+use crate::{
+    analyzer::Finding,
+    output::{FetchedUrl, OutputRecord, OutputSink},
+    web::html::PageText,
+};
+
+/// Writes crawl results to the output database, one transaction per batch.
+///
+/// Every sink records its results under a new crawl session.
+pub struct DatabaseSink {
+    db: Connection,
+    bodies: BodyStore,
+    session_id: i64,
+}
+
+impl DatabaseSink {
+    /// Opens the output database for writing.
+    pub fn open(db_path: &Path, max_stored_body_size: usize, train_body_dictionary: bool) -> Result<Self, DbError> {
+        let db = super::open(db_path)?;
+
+        // WAL lets readers inspect the output while the crawl is running
+        db.query_row("PRAGMA journal_mode = WAL", [], |row| row.get::<_, String>(0))?;
+        db.pragma_update(None, "synchronous", "NORMAL")?;
+
+        let bodies = BodyStore::new(&db, max_stored_body_size, train_body_dictionary)?;
+
+        db.execute("INSERT INTO sessions (started_at) VALUES (?1)", [now()])?;
+        let session_id = db.last_insert_rowid();
+
+        Ok(DatabaseSink { db, bodies, session_id })
+    }
+
+    /// Records the settings and targets the session was started with, one target per line.
+    pub fn describe_session(&self, settings: &str, targets: &[String]) -> Result<(), DbError> {
+        self.db.execute(
+            "UPDATE sessions SET settings = ?1, targets = ?2 WHERE id = ?3",
+            params![settings, targets.join("\n"), self.session_id],
+        )?;
+
+        Ok(())
+    }
+
+    fn write_response(db: &Transaction, bodies: &mut BodyStore, session_id: i64, fetched: &FetchedUrl) -> Result<(), DbError> {
+        let host_id = Self::host_id(db, &fetched.host)?;
+        let url_id = Self::url_id(db, host_id, &fetched.url)?;
+        let body_hash = bodies.store(db, &fetched.body)?;
+
+        db.prepare_cached(
+            "INSERT INTO responses (url_id, session_id, method, status, http_version, fetched_at, wait_ms, duration_ms, body_hash, truncated, encoding)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        )?
+        .execute(params![
+            url_id,
+            session_id,
+            fetched.method,
+            fetched.status,
+            fetched.http_version,
+            fetched.fetched_at,
+            fetched.wait_ms,
+            fetched.duration_ms,
+            body_hash,
+            fetched.truncated,
+            fetched.encoding
+        ])?;
+        let response_id = db.last_insert_rowid();
+
+        let mut insert_header =
+            db.prepare_cached("INSERT INTO headers (response_id, direction, name, value) VALUES (?1, ?2, ?3, ?4)")?;
+        for (name, value) in &fetched.request_headers {
+            insert_header.execute(params![response_id, "request", name, value])?;
+        }
+        for (name, value) in &fetched.headers {
+            insert_header.execute(params![response_id, "response", name, value])?;
+        }
+
+        Self::write_analysis(db, response_id, &fetched.url, &fetched.page, &fetched.links, &fetched.findings)?;
+
+        Ok(())
+    }
+
+    /// Replaces what was learned from a stored response with the results of processing it again.
+    pub fn replace_analysis(
+        db: &Transaction,
+        response_id: i64,
+        url: &str,
+        page: &PageText,
+        links: &[String],
+        findings: &[Finding],
+    ) -> Result<(), DbError> {
+        db.prepare_cached("DELETE FROM page_text WHERE rowid = ?1")?.execute([response_id])?;
+        db.prepare_cached("DELETE FROM links WHERE response_id = ?1")?.execute([response_id])?;
+        db.prepare_cached("DELETE FROM findings WHERE response_id = ?1")?.execute([response_id])?;
+
+        Self::write_analysis(db, response_id, url, page, links, findings)
+    }
+
+    /// Writes what was learned from a response: its indexed text, links and findings.
+    fn write_analysis(
+        db: &Transaction,
+        response_id: i64,
+        url: &str,
+        page: &PageText,
+        links: &[String],
+        findings: &[Finding],
+    ) -> Result<(), DbError> {
+        db.prepare_cached("INSERT INTO page_text (rowid, url, title, text) VALUES (?1, ?2, ?3, ?4)")?
+            .execute(params![response_id, url, page.title, page.text])?;
+
+        let mut insert_link = db.prepare_cached("INSERT INTO links (response_id, url) VALUES (?1, ?2)")?;
+        for link in links {
+            insert_link.execute(params![response_id, link])?;
+        }
+
+        let mut insert_finding = db.prepare_cached("INSERT INTO findings (response_id, kind, detail) VALUES (?1, ?2, ?3)")?;
+        for finding in findings {
+            insert_finding.execute(params![response_id, finding.kind, finding.detail])?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the ID of a host, inserting it if it is new.
+    fn host_id(db: &Transaction, host: &str) -> Result<i64, rusqlite::Error> {
+        db.prepare_cached("INSERT OR IGNORE INTO hosts (host) VALUES (?1)")?.execute([host])?;
+        db.prepare_cached("SELECT id FROM hosts WHERE host = ?1")?.query_row([host], |row| row.get(0))
+    }
+
+    /// Returns the ID of a URL, inserting it if it is new.
+    fn url_id(db: &Transaction, host_id: i64, url: &str) -> Result<i64, rusqlite::Error> {
+        db.prepare_cached("INSERT OR IGNORE INTO urls (host_id, url) VALUES (?1, ?2)")?.execute(params![host_id, url])?;
+        db.prepare_cached("SELECT id FROM urls WHERE url = ?1")?.query_row([url], |row| row.get(0))
+    }
+}
+
+impl OutputSink for DatabaseSink {
+    fn write_batch(&mut self, batch: &[OutputRecord]) -> Result<(), Box<dyn Error>> {
+        let transaction = self.db.transaction()?;
+
+        for record in batch {
+            match record {
+                // A host keeps the source it was first discovered through
+                OutputRecord::Host { host, source } => {
+                    transaction
+                        .prepare_cached("INSERT OR IGNORE INTO hosts (host, source) VALUES (?1, ?2)")?
+                        .execute(params![host, source])?;
+                }
+                OutputRecord::Service { host, scheme, port, probed_at, status, error } => {
+                    let host_id = Self::host_id(&transaction, host)?;
+                    transaction
+                        .prepare_cached(
+                            "INSERT INTO services (host_id, session_id, scheme, port, probed_at, status, error)
+                            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                        )?
+                        .execute(params![host_id, self.session_id, scheme, port, probed_at, status, error])?;
+                }
+                OutputRecord::Response(fetched) => {
+                    Self::write_response(&transaction, &mut self.bodies, self.session_id, fetched)?
+                }
+                OutputRecord::Failure { url, host, fetched_at, attempts, error, detail } => {
+                    let host_id = Self::host_id(&transaction, host)?;
+                    let url_id = Self::url_id(&transaction, host_id, url)?;
+                    transaction
+                        .prepare_cached(
+                            "INSERT INTO fetch_errors (url_id, session_id, fetched_at, attempts, kind, detail)
+                            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                        )?
+                        .execute(params![url_id, self.session_id, fetched_at, attempts, error.as_str(), detail])?;
+                }
+                OutputRecord::Certificates { host, port, seen_at, chain, error } => {
+                    let host_id = Self::host_id(&transaction, host)?;
+                    let mut insert = transaction.prepare_cached(
+                        "INSERT INTO certificates (host_id, port, session_id, seen_at, position, subject, issuer, serial,
+                            not_before, not_after, subject_alt_names, sha256, der, error)
+                        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+                    )?;
+
+                    for (position, certificate) in chain.iter().enumerate() {
+                        // The verification error concerns the chain, and is kept with the host's own certificate
+                        let error = if position == 0 { error.as_deref() } else { None };
+                        insert.execute(params![
+                            host_id,
+                            port,
+                            self.session_id,
+                            seen_at,
+                            position,
+                            certificate.subject,
+                            certificate.issuer,
+                            certificate.serial,
+                            certificate.not_before,
+                            certificate.not_after,
+                            serde_json::to_string(&certificate.subject_alt_names)?,
+                            certificate.sha256,
+                            certificate.der,
+                            error
+                        ])?;
+                    }
+                }
+            }
+        }
+
+        Ok(transaction.commit()?)
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), Box<dyn Error>> {
+        self.db.execute("UPDATE sessions SET finished_at = ?1 WHERE id = ?2", params![now(), self.session_id])?;
+        self.db.close().map_err(|(_, error)| error)?;
+        Ok(())
+    }
+}
+
+fn now() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)
+}
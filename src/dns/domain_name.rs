@@ -15,25 +15,26 @@ impl DomainName {
         let domain_levels_len = domain_levels.len();
 
         if domain_levels_len < 2 {
-            return Err(DomainNameParseError);
+            Err(DomainNameParseError)
         }
         else if domain_levels_len == 2 {
-            return Ok(Self {
+            Ok(Self {
                 tld: domain_levels.last().unwrap().to_owned().to_string(),
                 domain: domain_levels.first().unwrap().to_owned().to_string(),
                 subdomains: None
-            });
+            })
         }
         else {
-            return Ok(Self {
+            Ok(Self {
                 tld: domain_levels.last().unwrap().to_owned().to_string(),
                 domain: domain_levels[domain_levels_len - 2].to_string(),
                 subdomains: Some(domain_levels[0..domain_levels.len() - 2].iter().map(|v| v.to_string()).collect())
-            });
+            })
         }
     }
 
     /// Returns whether the domain name is a subdomain of another domain name.
+    #[allow(dead_code)]
     pub fn is_subdomain_of(&self, other: &DomainName) -> bool {
         if self.domain.ne(other.domain()) || self.tld.ne(other.tld()) { return false; }
 
@@ -63,8 +64,9 @@ impl DomainName {
     }
 
     /// Returns whether the domain name is superdomain of another domain name
+    #[allow(dead_code)]
     pub fn is_superdomain_of(&self, other: &DomainName) -> bool {
-        other.is_subdomain_of(&self)
+        other.is_subdomain_of(self)
     }
 
     /// Returns the top-level domain of the domain name.
@@ -78,6 +80,7 @@ impl DomainName {
     }
 
     /// Returns the subdomains of the domain, if such exist.
+    #[allow(dead_code)]
     pub fn subdomains(&self) -> &Option<Vec<String>> {
         &self.subdomains
     }
@@ -0,0 +1,83 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use rusqlite::Connection;
+
+use crate::db::DbError;
+
+/// What changed between two crawl sessions
+#[derive(Debug, Default)]
+pub struct SessionDiff {
+    /// URLs only fetched in the newer session, with their status
+    pub added: Vec<(String, u16)>,
+    /// URLs only fetched in the older session, with their status
+    pub removed: Vec<(String, u16)>,
+    /// URLs whose status changed, with the old and new status
+    pub changed: Vec<(String, u16, u16)>,
+    /// Findings only reported in the newer session, as URL, kind and detail
+    pub new_findings: Vec<(String, String, String)>,
+    /// Findings only reported in the older session, as URL, kind and detail
+    pub resolved_findings: Vec<(String, String, String)>,
+}
+
+/// Compares the URLs and findings of two sessions.
+pub fn diff_sessions(db: &Connection, from: i64, to: i64) -> Result<SessionDiff, DbError> {
+    let (old_statuses, old_findings) = (statuses(db, from)?, findings(db, from)?);
+    let (new_statuses, new_findings) = (statuses(db, to)?, findings(db, to)?);
+
+    let mut diff = SessionDiff::default();
+    for (url, &status) in &new_statuses {
+        match old_statuses.get(url) {
+            None => diff.added.push((url.clone(), status)),
+            Some(&old_status) if old_status != status => diff.changed.push((url.clone(), old_status, status)),
+            Some(_) => {}
+        }
+    }
+    for (url, &status) in &old_statuses {
+        if !new_statuses.contains_key(url) {
+            diff.removed.push((url.clone(), status));
+        }
+    }
+
+    diff.new_findings = new_findings.difference(&old_findings).cloned().collect();
+    diff.resolved_findings = old_findings.difference(&new_findings).cloned().collect();
+
+    Ok(diff)
+}
+
+/// Returns the IDs of the last two sessions that fetched anything, oldest first.
+pub fn last_two_sessions(db: &Connection) -> Result<Option<(i64, i64)>, DbError> {
+    let sessions: Vec<i64> = db
+        .prepare("SELECT DISTINCT session_id FROM responses WHERE session_id IS NOT NULL ORDER BY session_id DESC LIMIT 2")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+
+    Ok(match sessions[..] {
+        [to, from] => Some((from, to)),
+        _ => None,
+    })
+}
+
+/// The status of the last response to each URL fetched in a session
+fn statuses(db: &Connection, session: i64) -> Result<BTreeMap<String, u16>, DbError> {
+    let mut select = db.prepare(
+        "SELECT urls.url, responses.status FROM responses
+        JOIN urls ON urls.id = responses.url_id
+        WHERE responses.session_id = ?1
+        ORDER BY responses.id",
+    )?;
+    let rows = select.query_map([session], |row| Ok((row.get(0)?, row.get::<_, Option<u16>>(1)?.unwrap_or_default())))?;
+
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+fn findings(db: &Connection, session: i64) -> Result<BTreeSet<(String, String, String)>, DbError> {
+    let mut select = db.prepare(
+        "SELECT urls.url, findings.kind, COALESCE(findings.detail, '') FROM findings
+        JOIN responses ON responses.id = findings.response_id
+        JOIN urls ON urls.id = responses.url_id
+        WHERE responses.session_id = ?1",
+    )?;
+    let rows = select.query_map([session], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+
+    Ok(rows.collect::<Result<_, _>>()?)
+}
@@ -0,0 +1,188 @@
+use std::error::Error;
+
+use base64::Engine;
+use reqwest::StatusCode;
+use rusqlite::Connection;
+use serde::Serialize;
+use url::Url;
+
+use super::{stored, ExportFilter};
+use crate::web::{charset, http};
+
+/// An HTTP Archive 1.2 document
+#[derive(Serialize)]
+pub struct Har {
+    log: HarLog,
+}
+
+#[derive(Serialize)]
+struct HarLog {
+    version: &'static str,
+    creator: HarCreator,
+    entries: Vec<HarEntry>,
+}
+
+#[derive(Serialize)]
+struct HarCreator {
+    name: &'static str,
+    version: &'static str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct HarEntry {
+    started_date_time: String,
+    time: u64,
+    request: HarRequest,
+    response: HarResponse,
+    cache: HarCache,
+    timings: HarTimings,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct HarRequest {
+    method: String,
+    url: String,
+    http_version: String,
+    cookies: Vec<HarNameValue>,
+    headers: Vec<HarNameValue>,
+    query_string: Vec<HarNameValue>,
+    headers_size: i64,
+    body_size: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct HarResponse {
+    status: u16,
+    status_text: String,
+    http_version: String,
+    cookies: Vec<HarNameValue>,
+    headers: Vec<HarNameValue>,
+    content: HarContent,
+    #[serde(rename = "redirectURL")]
+    redirect_url: String,
+    headers_size: i64,
+    body_size: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct HarContent {
+    size: usize,
+    mime_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    encoding: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    comment: Option<&'static str>,
+}
+
+#[derive(Serialize)]
+struct HarNameValue {
+    name: String,
+    value: String,
+}
+
+#[derive(Serialize)]
+struct HarCache {}
+
+#[derive(Serialize)]
+struct HarTimings {
+    send: u64,
+    wait: u64,
+    receive: u64,
+}
+
+/// Build a HAR document from the exchanges stored in an output database
+pub fn build_har(db: &Connection, filter: &ExportFilter) -> Result<Har, Box<dyn Error>> {
+    let mut entries = Vec::new();
+    stored::for_each_response(db, filter, |fetched| {
+        let header_value = |name: &str| {
+            fetched
+                .headers
+                .iter()
+                .find(|(header_name, _)| header_name.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.clone())
+                .unwrap_or_default()
+        };
+        let name_values = |headers: Vec<(String, String)>| {
+            headers.into_iter().map(|(name, value)| HarNameValue { name, value }).collect()
+        };
+
+        entries.push(HarEntry {
+            started_date_time: fetched.fetched_at,
+            time: fetched.duration_ms,
+            request: HarRequest {
+                method: fetched.method,
+                query_string: query_string(&fetched.url),
+                url: fetched.url,
+                http_version: fetched.http_version.clone(),
+                cookies: Vec::new(),
+                headers: name_values(fetched.request_headers),
+                headers_size: -1,
+                body_size: 0,
+            },
+            response: HarResponse {
+                status: fetched.status,
+                status_text: StatusCode::from_u16(fetched.status)
+                    .ok()
+                    .and_then(|status| status.canonical_reason())
+                    .unwrap_or_default()
+                    .to_string(),
+                http_version: fetched.http_version,
+                cookies: Vec::new(),
+                content: content(header_value("content-type"), fetched.body, fetched.encoding.as_deref(), fetched.truncated),
+                redirect_url: header_value("location"),
+                headers: name_values(fetched.headers),
+                headers_size: -1,
+                body_size: -1,
+            },
+            cache: HarCache {},
+            timings: HarTimings {
+                send: 0,
+                wait: fetched.wait_ms,
+                receive: fetched.duration_ms.saturating_sub(fetched.wait_ms),
+            },
+        });
+        Ok(())
+    })?;
+
+    Ok(Har {
+        log: HarLog {
+            version: "1.2",
+            creator: HarCreator {
+                name: env!("CARGO_PKG_NAME"),
+                version: env!("CARGO_PKG_VERSION"),
+            },
+            entries,
+        },
+    })
+}
+
+fn query_string(url: &str) -> Vec<HarNameValue> {
+    let Ok(url) = Url::parse(url) else { return Vec::new(); };
+
+    url.query_pairs()
+        .map(|(name, value)| HarNameValue { name: name.into_owned(), value: value.into_owned() })
+        .collect()
+}
+
+/// The response content. Text is decoded from the encoding it was stored in, anything else is base64 encoded.
+fn content(mime_type: String, body: Vec<u8>, body_encoding: Option<&str>, truncated: bool) -> HarContent {
+    let size = body.len();
+
+    let (text, encoding) = if body.is_empty() {
+        (None, None)
+    } else if http::is_textual(&mime_type) || std::str::from_utf8(&body).is_ok() {
+        (Some(charset::decode_stored(&body, body_encoding)), None)
+    } else {
+        (Some(base64::engine::general_purpose::STANDARD.encode(body)), Some("base64"))
+    };
+
+    let comment = truncated.then_some("Truncated at the maximum body size");
+
+    HarContent { size, mime_type, text, encoding, comment }
+}
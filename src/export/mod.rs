@@ -0,0 +1,19 @@
+pub mod diff;
+pub mod har;
+pub mod report;
+pub mod stats;
+pub mod stored;
+
+/// SQL condition implementing an `ExportFilter`, with the target bound to `?1` and the session to `?2`.
+/// The query must join `hosts` and `responses`.
+pub const FILTER_CONDITION: &str =
+    "(?1 IS NULL OR hosts.host = ?1 OR hosts.host LIKE '%.' || ?1) AND (?2 IS NULL OR responses.session_id = ?2)";
+
+/// Which stored results to export
+#[derive(Debug, Clone, Default)]
+pub struct ExportFilter {
+    /// Only results for this host or one of its subdomains
+    pub target: Option<String>,
+    /// Only results from this crawl session
+    pub session: Option<i64>,
+}
@@ -0,0 +1,289 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use rusqlite::{params, Connection};
+use url::Url;
+
+use super::{ExportFilter, FILTER_CONDITION};
+use crate::db::DbError;
+
+/// Path fragments that make an endpoint worth a closer look
+const INTERESTING_PATH_FRAGMENTS: [&str; 22] = [
+    "admin", "login", "signin", "logout", "register", "password", "reset", "token", "oauth", "api", "graphql",
+    "swagger", "debug", "console", "actuator", "config", "backup", "upload", "phpinfo", ".git", ".env", ".bak",
+];
+
+/// A summary of the crawl results for a target or session
+#[derive(Debug)]
+pub struct Report {
+    pub title: String,
+    /// Hosts and the number of URLs fetched from each
+    pub hosts: Vec<(String, u64)>,
+    /// Number of responses per status code
+    pub status_codes: Vec<(Option<u16>, u64)>,
+    /// URLs with their status and why they are interesting
+    pub interesting_endpoints: Vec<(String, Option<u16>, String)>,
+    /// Hosts outside the report linked to, with the number of links
+    pub external_hosts: Vec<(String, u64)>,
+    /// URL, kind and detail of every finding
+    pub findings: Vec<(String, String, String)>,
+}
+
+/// Returns the hosts that are not a subdomain of another stored host, i.e. the crawl targets
+pub fn targets(db: &Connection) -> Result<Vec<String>, DbError> {
+    let mut select = db.prepare(
+        "SELECT host FROM hosts AS child
+        WHERE NOT EXISTS (SELECT 1 FROM hosts AS parent WHERE child.host LIKE '%.' || parent.host)
+        ORDER BY host",
+    )?;
+    let targets = select.query_map([], |row| row.get(0))?.collect::<Result<_, _>>()?;
+
+    Ok(targets)
+}
+
+/// Returns the IDs of all crawl sessions
+pub fn sessions(db: &Connection) -> Result<Vec<i64>, DbError> {
+    let mut select = db.prepare("SELECT id FROM sessions ORDER BY id")?;
+    let sessions = select.query_map([], |row| row.get(0))?.collect::<Result<_, _>>()?;
+
+    Ok(sessions)
+}
+
+/// Summarize the stored results matching a filter
+pub fn build_report(db: &Connection, title: &str, filter: &ExportFilter) -> Result<Report, DbError> {
+    let filter_params = params![filter.target, filter.session];
+    let joins = "JOIN urls ON urls.id = responses.url_id JOIN hosts ON hosts.id = urls.host_id";
+    let from = format!("FROM responses {} WHERE {}", joins, FILTER_CONDITION);
+
+    let hosts: Vec<(String, u64)> = db
+        .prepare(&format!("SELECT hosts.host, COUNT(DISTINCT urls.id) {} GROUP BY hosts.host ORDER BY hosts.host", from))?
+        .query_map(filter_params, |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+
+    let status_codes = db
+        .prepare(&format!("SELECT responses.status, COUNT(*) {} GROUP BY responses.status ORDER BY responses.status", from))?
+        .query_map(filter_params, |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+
+    // The latest status of every URL
+    let mut urls: BTreeMap<String, Option<u16>> = BTreeMap::new();
+    let mut select_urls = db.prepare(&format!("SELECT urls.url, responses.status {} ORDER BY responses.id", from))?;
+    let mut rows = select_urls.query(filter_params)?;
+    while let Some(row) = rows.next()? {
+        urls.insert(row.get(0)?, row.get(1)?);
+    }
+
+    let interesting_endpoints = urls
+        .into_iter()
+        .filter_map(|(url, status)| interest(&url, status).map(|reason| (url, status, reason)))
+        .collect();
+
+    let report_hosts: BTreeSet<&str> = hosts.iter().map(|(host, _)| host.as_str()).collect();
+    let mut external_hosts: BTreeMap<String, u64> = BTreeMap::new();
+    let mut select_links = db.prepare(&format!(
+        "SELECT links.url FROM links JOIN responses ON responses.id = links.response_id {} WHERE {}",
+        joins, FILTER_CONDITION
+    ))?;
+    let mut rows = select_links.query(filter_params)?;
+    while let Some(row) = rows.next()? {
+        let link: String = row.get(0)?;
+        let Some(host) = Url::parse(&link).ok().and_then(|link| link.host_str().map(str::to_string)) else { continue; };
+
+        if !report_hosts.contains(host.as_str()) {
+            *external_hosts.entry(host).or_default() += 1;
+        }
+    }
+
+    let findings = db
+        .prepare(&format!(
+            "SELECT urls.url, findings.kind, COALESCE(findings.detail, '')
+            FROM findings JOIN responses ON responses.id = findings.response_id {} WHERE {}
+            ORDER BY findings.kind, urls.url",
+            joins, FILTER_CONDITION
+        ))?
+        .query_map(filter_params, |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .collect::<Result<_, _>>()?;
+
+    Ok(Report {
+        title: title.to_string(),
+        hosts,
+        status_codes,
+        interesting_endpoints,
+        external_hosts: external_hosts.into_iter().collect(),
+        findings,
+    })
+}
+
+/// Why a URL is interesting, if it is
+fn interest(url: &str, status: Option<u16>) -> Option<String> {
+    let path = Url::parse(url).map(|url| url.path().to_lowercase()).unwrap_or_default();
+
+    if let Some(fragment) = INTERESTING_PATH_FRAGMENTS.iter().find(|fragment| path.contains(*fragment)) {
+        return Some(format!("path contains '{}'", fragment));
+    }
+
+    match status {
+        Some(401) | Some(403) => Some("access denied".to_string()),
+        Some(status) if status >= 500 => Some("server error".to_string()),
+        _ => None,
+    }
+}
+
+fn status_text(status: Option<u16>) -> String {
+    status.map(|status| status.to_string()).unwrap_or_else(|| "none".to_string())
+}
+
+/// Write each table of a report as a CSV file into a directory
+pub fn write_csv(report: &Report, dir: &Path) -> Result<(), io::Error> {
+    fs::create_dir_all(dir)?;
+
+    let mut hosts = csv::Writer::from_path(dir.join("hosts.csv"))?;
+    hosts.write_record(["host", "urls"])?;
+    for (host, urls) in &report.hosts {
+        hosts.write_record([host, &urls.to_string()])?;
+    }
+    hosts.flush()?;
+
+    let mut status_codes = csv::Writer::from_path(dir.join("status_codes.csv"))?;
+    status_codes.write_record(["status", "responses"])?;
+    for (status, count) in &report.status_codes {
+        status_codes.write_record([status_text(*status), count.to_string()])?;
+    }
+    status_codes.flush()?;
+
+    let mut endpoints = csv::Writer::from_path(dir.join("interesting_endpoints.csv"))?;
+    endpoints.write_record(["url", "status", "reason"])?;
+    for (url, status, reason) in &report.interesting_endpoints {
+        endpoints.write_record([url, &status_text(*status), reason])?;
+    }
+    endpoints.flush()?;
+
+    let mut external_hosts = csv::Writer::from_path(dir.join("external_hosts.csv"))?;
+    external_hosts.write_record(["host", "links"])?;
+    for (host, links) in &report.external_hosts {
+        external_hosts.write_record([host, &links.to_string()])?;
+    }
+    external_hosts.flush()?;
+
+    let mut findings = csv::Writer::from_path(dir.join("findings.csv"))?;
+    findings.write_record(["url", "kind", "detail"])?;
+    for (url, kind, detail) in &report.findings {
+        findings.write_record([url, kind, detail])?;
+    }
+    findings.flush()?;
+
+    Ok(())
+}
+
+/// A table of a rendered report
+struct Table {
+    heading: &'static str,
+    columns: Vec<&'static str>,
+    rows: Vec<Vec<String>>,
+}
+
+/// The report's tables, in the order they are rendered
+fn tables(report: &Report) -> Vec<Table> {
+    vec![
+        Table {
+            heading: "Hosts",
+            columns: vec!["Host", "URLs"],
+            rows: report.hosts.iter().map(|(host, urls)| vec![host.clone(), urls.to_string()]).collect(),
+        },
+        Table {
+            heading: "Responses by status code",
+            columns: vec!["Status", "Responses"],
+            rows: report.status_codes.iter().map(|(status, count)| vec![status_text(*status), count.to_string()]).collect(),
+        },
+        Table {
+            heading: "Interesting endpoints",
+            columns: vec!["URL", "Status", "Reason"],
+            rows: report
+                .interesting_endpoints
+                .iter()
+                .map(|(url, status, reason)| vec![url.clone(), status_text(*status), reason.clone()])
+                .collect(),
+        },
+        Table {
+            heading: "External hosts",
+            columns: vec!["Host", "Links"],
+            rows: report.external_hosts.iter().map(|(host, links)| vec![host.clone(), links.to_string()]).collect(),
+        },
+        Table {
+            heading: "Findings",
+            columns: vec!["URL", "Kind", "Detail"],
+            rows: report.findings.iter().map(|(url, kind, detail)| vec![url.clone(), kind.clone(), detail.clone()]).collect(),
+        },
+    ]
+}
+
+/// Render a report as a Markdown document
+pub fn render_markdown(report: &Report) -> String {
+    let escape = |cell: &str| cell.replace('|', "\\|").replace(['\r', '\n'], " ");
+    let mut markdown = format!("# {}\n", report.title);
+
+    for table in tables(report) {
+        let _ = write!(markdown, "\n## {}\n\n", table.heading);
+
+        if table.rows.is_empty() {
+            markdown.push_str("None.\n");
+            continue;
+        }
+
+        let _ = writeln!(markdown, "| {} |", table.columns.join(" | "));
+        let _ = writeln!(markdown, "|{}", " --- |".repeat(table.columns.len()));
+        for row in table.rows {
+            let cells: Vec<String> = row.iter().map(|cell| escape(cell)).collect();
+            let _ = writeln!(markdown, "| {} |", cells.join(" | "));
+        }
+    }
+
+    markdown
+}
+
+/// Render a report as a standalone HTML page
+pub fn render_html(report: &Report) -> String {
+    let escape = |text: &str| {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+    };
+
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{0}</title>\n\
+        <style>body {{ font-family: sans-serif; }} table {{ border-collapse: collapse; }} \
+        td, th {{ border: 1px solid #ccc; padding: 4px 8px; text-align: left; }}</style>\n</head>\n<body>\n<h1>{0}</h1>\n",
+        escape(&report.title)
+    );
+
+    for table in tables(report) {
+        let _ = writeln!(html, "<h2>{}</h2>", table.heading);
+
+        if table.rows.is_empty() {
+            html.push_str("<p>None.</p>\n");
+            continue;
+        }
+
+        html.push_str("<table>\n<tr>");
+        for column in &table.columns {
+            let _ = write!(html, "<th>{}</th>", column);
+        }
+        html.push_str("</tr>\n");
+
+        for row in table.rows {
+            html.push_str("<tr>");
+            for cell in row {
+                let _ = write!(html, "<td>{}</td>", escape(&cell));
+            }
+            html.push_str("</tr>\n");
+        }
+        html.push_str("</table>\n");
+    }
+
+    html.push_str("</body>\n</html>\n");
+    html
+}
@@ -0,0 +1,45 @@
+use rusqlite::Connection;
+
+use crate::db::DbError;
+
+/// Counts describing the contents of an output database
+#[derive(Debug, Default)]
+pub struct Stats {
+    pub sessions: u64,
+    pub hosts: u64,
+    pub live_services: u64,
+    pub urls: u64,
+    pub responses: u64,
+    pub stored_bodies: u64,
+    /// The size of the stored bodies before compression
+    pub body_bytes: u64,
+    /// The size of the stored bodies after compression
+    pub stored_bytes: u64,
+    /// Responses per status class, such as `2xx`
+    pub status_classes: Vec<(String, u64)>,
+    /// Findings per kind
+    pub findings: Vec<(String, u64)>,
+}
+
+/// Collects the statistics of an output database.
+pub fn collect(db: &Connection) -> Result<Stats, DbError> {
+    let count = |sql: &str| db.query_row(sql, [], |row| row.get::<_, u64>(0));
+    let grouped = |sql: &str| -> Result<Vec<(String, u64)>, rusqlite::Error> {
+        db.prepare(sql)?.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?.collect()
+    };
+
+    Ok(Stats {
+        sessions: count("SELECT COUNT(*) FROM sessions")?,
+        hosts: count("SELECT COUNT(*) FROM hosts")?,
+        live_services: count("SELECT COUNT(*) FROM services WHERE status IS NOT NULL")?,
+        urls: count("SELECT COUNT(*) FROM urls")?,
+        responses: count("SELECT COUNT(*) FROM responses")?,
+        stored_bodies: count("SELECT COUNT(*) FROM blobs")?,
+        body_bytes: count("SELECT COALESCE(SUM(size), 0) FROM blobs")?,
+        stored_bytes: count("SELECT COALESCE(SUM(LENGTH(data)), 0) FROM blobs")?,
+        status_classes: grouped(
+            "SELECT COALESCE((status / 100) || 'xx', 'none') AS class, COUNT(*) FROM responses GROUP BY class ORDER BY class",
+        )?,
+        findings: grouped("SELECT kind, COUNT(*) FROM findings GROUP BY kind ORDER BY COUNT(*) DESC, kind")?,
+    })
+}
@@ -0,0 +1,94 @@
+use std::error::Error;
+
+use rusqlite::{params, Connection};
+
+use super::{ExportFilter, FILTER_CONDITION};
+use crate::{
+    analyzer::Finding,
+    db::blob::BodyReader,
+    output::FetchedUrl,
+    web::html::PageText,
+};
+
+/// Rebuilds each stored response matching the filter as it was when fetched, in the order they were fetched.
+///
+/// This lets the output sinks export a database after the fact. The target of a rebuilt response is its host.
+pub fn for_each_response(
+    db: &Connection,
+    filter: &ExportFilter,
+    mut f: impl FnMut(FetchedUrl) -> Result<(), Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>> {
+    let mut bodies = BodyReader::default();
+    let mut select_headers = db.prepare("SELECT direction, name, value FROM headers WHERE response_id = ?1 ORDER BY id")?;
+    let mut select_page = db.prepare("SELECT title, text FROM page_text WHERE rowid = ?1")?;
+    let mut select_links = db.prepare("SELECT url FROM links WHERE response_id = ?1 ORDER BY id")?;
+    let mut select_findings = db.prepare("SELECT kind, COALESCE(detail, '') FROM findings WHERE response_id = ?1 ORDER BY id")?;
+    let mut select_responses = db.prepare(&format!(
+        "SELECT responses.id, urls.url, hosts.host, responses.status, responses.http_version, responses.fetched_at,
+            responses.wait_ms, responses.duration_ms, responses.body_hash, responses.method, responses.truncated,
+            responses.encoding
+        FROM responses
+        JOIN urls ON urls.id = responses.url_id
+        JOIN hosts ON hosts.id = urls.host_id
+        WHERE {}
+        ORDER BY responses.fetched_at, responses.id",
+        FILTER_CONDITION
+    ))?;
+    let mut rows = select_responses.query(params![filter.target, filter.session])?;
+
+    while let Some(row) = rows.next()? {
+        let response_id: i64 = row.get(0)?;
+        let host: String = row.get(2)?;
+        let body_hash: Option<String> = row.get(8)?;
+
+        let mut request_headers = Vec::new();
+        let mut headers = Vec::new();
+        let mut header_rows = select_headers.query([response_id])?;
+        while let Some(header) = header_rows.next()? {
+            let direction: String = header.get(0)?;
+            let header = (header.get(1)?, header.get(2)?);
+
+            if direction == "request" {
+                request_headers.push(header);
+            } else {
+                headers.push(header);
+            }
+        }
+
+        let page = select_page
+            .query_row([response_id], |page| Ok(PageText { title: page.get(0)?, text: page.get(1)? }))
+            .unwrap_or_default();
+        let links = select_links.query_map([response_id], |link| link.get(0))?.collect::<Result<_, _>>()?;
+        let findings = select_findings
+            .query_map([response_id], |finding| Ok(Finding { kind: finding.get(0)?, detail: finding.get(1)? }))?
+            .collect::<Result<_, _>>()?;
+
+        let body = match &body_hash {
+            Some(hash) => bodies.read(db, hash)?.unwrap_or_default(),
+            None => Vec::new(),
+        };
+        let wait_ms: u64 = row.get::<_, Option<u64>>(6)?.unwrap_or_default();
+
+        f(FetchedUrl {
+            url: row.get(1)?,
+            target: host.clone(),
+            host,
+            method: row.get(9)?,
+            status: row.get::<_, Option<u16>>(3)?.unwrap_or_default(),
+            http_version: row.get::<_, Option<String>>(4)?.unwrap_or_else(|| "HTTP/1.1".to_string()),
+            request_headers,
+            fetched_at: row.get::<_, Option<String>>(5)?.unwrap_or_default(),
+            wait_ms,
+            duration_ms: row.get::<_, Option<u64>>(7)?.unwrap_or(wait_ms),
+            headers,
+            body,
+            encoding: row.get(11)?,
+            truncated: row.get(10)?,
+            page,
+            links,
+            findings,
+        })?;
+    }
+
+    Ok(())
+}
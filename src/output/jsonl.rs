@@ -0,0 +1,91 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use serde::Serialize;
+
+use super::{OutputRecord, OutputSink};
+
+/// Streams one JSON object per fetched URL to a file or stdout
+pub struct JsonLinesSink {
+    output: Box<dyn Write + Send>,
+}
+
+/// The JSON object written for a fetched URL
+#[derive(Serialize)]
+struct JsonLine<'a> {
+    url: &'a str,
+    target: &'a str,
+    status: u16,
+    truncated: bool,
+    fetched_at: &'a str,
+    title: Option<&'a str>,
+    headers: Vec<JsonHeader<'a>>,
+    timings: JsonTimings,
+    links: &'a [String],
+    findings: Vec<JsonFinding<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonHeader<'a> {
+    name: &'a str,
+    value: &'a str,
+}
+
+#[derive(Serialize)]
+struct JsonTimings {
+    wait_ms: u64,
+    duration_ms: u64,
+}
+
+#[derive(Serialize)]
+struct JsonFinding<'a> {
+    kind: &'a str,
+    detail: &'a str,
+}
+
+impl JsonLinesSink {
+    /// Creates a sink writing to a file, or to stdout if the path is `-`.
+    pub fn create(path: &Path) -> Result<Self, io::Error> {
+        let output: Box<dyn Write + Send> = if path == Path::new("-") {
+            Box::new(io::stdout())
+        } else {
+            Box::new(BufWriter::new(File::create(path)?))
+        };
+
+        Ok(JsonLinesSink { output })
+    }
+}
+
+impl OutputSink for JsonLinesSink {
+    fn write_batch(&mut self, batch: &[OutputRecord]) -> Result<(), Box<dyn Error>> {
+        for record in batch {
+            let OutputRecord::Response(fetched) = record else { continue; };
+
+            let line = JsonLine {
+                url: &fetched.url,
+                target: &fetched.target,
+                status: fetched.status,
+                truncated: fetched.truncated,
+                fetched_at: &fetched.fetched_at,
+                title: fetched.page.title.as_deref(),
+                headers: fetched.headers.iter().map(|(name, value)| JsonHeader { name, value }).collect(),
+                timings: JsonTimings { wait_ms: fetched.wait_ms, duration_ms: fetched.duration_ms },
+                links: &fetched.links,
+                findings: fetched.findings.iter().map(|finding| JsonFinding { kind: &finding.kind, detail: &finding.detail }).collect(),
+            };
+
+            serde_json::to_writer(&mut self.output, &line)?;
+            self.output.write_all(b"\n")?;
+        }
+
+        // Keep consumers at the other end of a pipe up to date
+        self.output.flush()?;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), Box<dyn Error>> {
+        Ok(self.output.flush()?)
+    }
+}
@@ -0,0 +1,160 @@
+pub mod jsonl;
+pub mod warc;
+
+use std::error::Error;
+use std::fmt;
+
+use tokio::{sync::mpsc, task::JoinHandle};
+
+use crate::{
+    analyzer::Finding,
+    web::{html::PageText, http::FetchError, tls::CertificateInfo},
+};
+
+/// The number of records that can be queued before the crawler has to wait for the output writer.
+const WRITE_QUEUE_CAPACITY: usize = 1024;
+
+/// The maximum number of records handed to the sinks at once.
+const MAX_BATCH_SIZE: usize = 256;
+
+/// A record produced by the crawler
+#[derive(Debug)]
+pub enum OutputRecord {
+    Host {
+        host: String,
+        source: &'static str, // How the host was discovered, such as `link` or `tls-san`
+    },
+    Service {
+        host: String,
+        scheme: String,
+        port: u16,
+        probed_at: String,
+        status: Option<u16>,   // The status of the probe response, if the service responded
+        error: Option<String>, // Why the probe failed, if it did
+    },
+    Response(Box<FetchedUrl>),
+    /// A URL that could not be fetched, even after retrying
+    Failure {
+        url: String,
+        host: String,
+        fetched_at: String, // When the first attempt was made
+        attempts: u32,
+        error: FetchError,
+        detail: String,
+    },
+    Certificates {
+        host: String,
+        port: u16,
+        seen_at: String,
+        chain: Vec<CertificateInfo>, // The server's own certificate first
+        error: Option<String>,       // Why the chain did not verify, if it did not
+    },
+}
+
+/// A fetched URL and everything learned from the response
+#[derive(Debug)]
+pub struct FetchedUrl {
+    pub url: String,
+    pub target: String,
+    pub host: String,
+    pub method: String,
+    pub status: u16,
+    pub http_version: String,
+    pub request_headers: Vec<(String, String)>,
+    pub fetched_at: String,
+    pub wait_ms: u64,
+    pub duration_ms: u64,
+    pub headers: Vec<(String, String)>,
+    /// The body as it was received
+    pub body: Vec<u8>,
+    /// The encoding the body was detected to be in, and decoded from for parsing
+    pub encoding: Option<String>,
+    /// Whether the body stops short, at the maximum body size
+    pub truncated: bool,
+    pub page: PageText,
+    pub links: Vec<String>,
+    pub findings: Vec<Finding>,
+}
+
+/// A destination for crawl results
+pub trait OutputSink: Send {
+    /// Persists a batch of records, in the order they were produced.
+    fn write_batch(&mut self, batch: &[OutputRecord]) -> Result<(), Box<dyn Error>>;
+
+    /// Flushes and closes the sink once the crawl is done.
+    fn finish(self: Box<Self>) -> Result<(), Box<dyn Error>>;
+}
+
+/// A handle used by the crawler tasks to queue records for the output writer.
+#[derive(Debug, Clone)]
+pub struct OutputHandle {
+    records: mpsc::Sender<OutputRecord>,
+}
+
+impl OutputHandle {
+    /// Queues a record for writing. Waits if the writer has fallen behind.
+    pub async fn write(&self, record: OutputRecord) -> Result<(), OutputError> {
+        self.records.send(record).await.map_err(|_| OutputError)
+    }
+}
+
+/// Hands batches of crawl records to every output sink from a single blocking thread.
+pub struct OutputWriter {
+    sinks: Vec<Box<dyn OutputSink>>,
+    records: mpsc::Receiver<OutputRecord>,
+}
+
+impl OutputWriter {
+    /// Starts the writer on a blocking thread.
+    ///
+    /// The writer stops once every handle has been dropped and all queued records have been written.
+    pub fn spawn(sinks: Vec<Box<dyn OutputSink>>) -> (OutputHandle, JoinHandle<()>) {
+        let (tx, records) = mpsc::channel(WRITE_QUEUE_CAPACITY);
+        let writer = OutputWriter { sinks, records };
+
+        (
+            OutputHandle { records: tx },
+            tokio::task::spawn_blocking(move || writer.run()),
+        )
+    }
+
+    fn run(mut self) {
+        let mut batch = Vec::with_capacity(MAX_BATCH_SIZE);
+
+        while let Some(record) = self.records.blocking_recv() {
+            batch.push(record);
+
+            // Drain whatever else is already queued into the same batch
+            while batch.len() < MAX_BATCH_SIZE {
+                let Ok(record) = self.records.try_recv() else { break; };
+                batch.push(record);
+            }
+
+            for sink in &mut self.sinks {
+                if let Err(error) = sink.write_batch(&batch) {
+                    tracing::error!("Failed to write output: {}", error);
+                }
+            }
+
+            batch.clear();
+        }
+
+        for sink in self.sinks {
+            if let Err(error) = sink.finish() {
+                tracing::error!("Failed to close output: {}", error);
+            }
+        }
+    }
+}
+
+/// Returned when a record is queued after the output writer has stopped.
+#[derive(Debug)]
+pub struct OutputError;
+
+impl std::error::Error for OutputError {}
+
+impl fmt::Display for OutputError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "The output writer has stopped!")
+    }
+}
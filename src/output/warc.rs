@@ -0,0 +1,375 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{SecondsFormat, Utc};
+use flate2::{read::MultiGzDecoder, write::GzEncoder, Compression};
+use reqwest::StatusCode;
+use url::Url;
+use uuid::Uuid;
+
+use super::{FetchedUrl, OutputRecord, OutputSink};
+
+/// Writes fetched URLs as gzipped WARC 1.1 records, starting a new file once the current one is large enough.
+pub struct WarcSink {
+    prefix: PathBuf,
+    max_file_size: u64,
+    serial: u32,
+    file: BufWriter<File>,
+    file_size: u64,
+}
+
+impl WarcSink {
+    /// Creates a sink writing `<prefix>-00000.warc.gz`, `<prefix>-00001.warc.gz` and so on.
+    pub fn create(prefix: &Path, max_file_size: u64) -> Result<Self, io::Error> {
+        let (file, file_size) = Self::open_file(prefix, 0)?;
+
+        Ok(WarcSink {
+            prefix: prefix.to_path_buf(),
+            max_file_size,
+            serial: 0,
+            file,
+            file_size,
+        })
+    }
+
+    /// Creates the WARC file with the given serial number and writes its warcinfo record.
+    fn open_file(prefix: &Path, serial: u32) -> Result<(BufWriter<File>, u64), io::Error> {
+        let mut file_name = prefix.file_name().unwrap_or_default().to_os_string();
+        file_name.push(format!("-{:05}.warc.gz", serial));
+        let path = prefix.with_file_name(&file_name);
+
+        let mut file = BufWriter::new(File::create(&path)?);
+
+        let info = format!(
+            "software: {}\r\nformat: WARC File Format 1.1\r\nconformsTo: http://iipc.github.io/warc-specifications/specifications/warc-format/warc-1.1/\r\n",
+            crate::web::http::USER_AGENT
+        );
+        let record = WarcRecord::new("warcinfo", "application/warc-fields", info.into_bytes())
+            .with_header("WARC-Date", &Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true))
+            .with_header("WARC-Filename", &file_name.to_string_lossy());
+        let file_size = record.write(&mut file)?;
+
+        Ok((file, file_size))
+    }
+
+    fn write_fetched(&mut self, fetched: &FetchedUrl) -> Result<(), io::Error> {
+        if self.file_size >= self.max_file_size {
+            self.file.flush()?;
+            self.serial += 1;
+            (self.file, self.file_size) = Self::open_file(&self.prefix, self.serial)?;
+        }
+
+        let response_id = record_id();
+        let date = &fetched.fetched_at;
+
+        let mut response = WarcRecord::with_id(response_id.clone(), "response", "application/http;msgtype=response", http_response(fetched))
+            .with_header("WARC-Target-URI", &fetched.url)
+            .with_header("WARC-Date", date);
+        if fetched.truncated {
+            response = response.with_header("WARC-Truncated", "length");
+        }
+
+        let request = WarcRecord::new("request", "application/http;msgtype=request", http_request(fetched))
+            .with_header("WARC-Target-URI", &fetched.url)
+            .with_header("WARC-Date", date)
+            .with_header("WARC-Concurrent-To", &response_id);
+
+        let metadata = WarcRecord::new("metadata", "application/warc-fields", metadata_fields(fetched))
+            .with_header("WARC-Target-URI", &fetched.url)
+            .with_header("WARC-Date", date)
+            .with_header("WARC-Refers-To", &response_id);
+
+        for record in [response, request, metadata] {
+            self.file_size += record.write(&mut self.file)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl OutputSink for WarcSink {
+    fn write_batch(&mut self, batch: &[OutputRecord]) -> Result<(), Box<dyn Error>> {
+        for record in batch {
+            if let OutputRecord::Response(fetched) = record {
+                self.write_fetched(fetched)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), Box<dyn Error>> {
+        Ok(self.file.flush()?)
+    }
+}
+
+/// A single WARC record
+pub struct WarcRecord {
+    headers: Vec<(String, String)>,
+    block: Vec<u8>,
+}
+
+impl WarcRecord {
+    fn new(warc_type: &str, content_type: &str, block: Vec<u8>) -> Self {
+        Self::with_id(record_id(), warc_type, content_type, block)
+    }
+
+    fn with_id(id: String, warc_type: &str, content_type: &str, block: Vec<u8>) -> Self {
+        WarcRecord {
+            headers: vec![
+                ("WARC-Type".to_string(), warc_type.to_string()),
+                ("WARC-Record-ID".to_string(), id),
+                ("Content-Type".to_string(), content_type.to_string()),
+            ],
+            block,
+        }
+    }
+
+    fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Returns the value of a WARC header of the record.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header_name, _)| header_name.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns the content block of the record.
+    pub fn block(&self) -> &[u8] {
+        &self.block
+    }
+
+    /// Writes the record as its own gzip member, returning the number of bytes written.
+    fn write(&self, output: &mut impl Write) -> Result<u64, io::Error> {
+        let mut record = GzEncoder::new(Vec::new(), Compression::default());
+
+        record.write_all(b"WARC/1.1\r\n")?;
+        for (name, value) in &self.headers {
+            write!(record, "{}: {}\r\n", name, value)?;
+        }
+        write!(record, "Content-Length: {}\r\n\r\n", self.block.len())?;
+        record.write_all(&self.block)?;
+        record.write_all(b"\r\n\r\n")?;
+
+        let record = record.finish()?;
+        output.write_all(&record)?;
+
+        Ok(record.len() as u64)
+    }
+}
+
+/// Reads the records of a WARC file, gzipped per record or not at all.
+pub struct WarcReader {
+    input: Box<dyn BufRead>,
+}
+
+impl WarcReader {
+    /// Opens a WARC file. Files ending in `.gz` are decompressed.
+    pub fn open(path: &Path) -> Result<Self, io::Error> {
+        let file = File::open(path)?;
+        let input: Box<dyn BufRead> = if path.extension().is_some_and(|extension| extension == "gz") {
+            Box::new(BufReader::new(MultiGzDecoder::new(file)))
+        } else {
+            Box::new(BufReader::new(file))
+        };
+
+        Ok(WarcReader { input })
+    }
+
+    fn read_record(&mut self) -> Result<Option<WarcRecord>, io::Error> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+
+        // Skip the blank lines separating records
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.input.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            if !line.trim().is_empty() {
+                break;
+            }
+        }
+
+        if !line.starts_with("WARC/") {
+            return Err(invalid("Expected a WARC record header"));
+        }
+
+        let mut headers = Vec::new();
+        loop {
+            line.clear();
+            if self.input.read_line(&mut line)? == 0 {
+                return Err(invalid("Truncated WARC record header"));
+            }
+
+            let header = line.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                headers.push((name.trim().to_string(), value.trim().to_string()));
+            }
+        }
+
+        let content_length: u64 = headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("Content-Length"))
+            .and_then(|(_, value)| value.parse().ok())
+            .ok_or_else(|| invalid("WARC record without a Content-Length"))?;
+
+        let mut block = Vec::new();
+        (&mut self.input).take(content_length).read_to_end(&mut block)?;
+
+        Ok(Some(WarcRecord { headers, block }))
+    }
+}
+
+impl Iterator for WarcReader {
+    type Item = Result<WarcRecord, io::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+/// An HTTP request as archived in a WARC request record
+#[derive(Debug)]
+pub struct HttpRequest {
+    pub method: String,
+    pub headers: Vec<(String, String)>,
+}
+
+/// Parse the HTTP request in the block of a WARC request record
+pub fn parse_http_request(block: &[u8]) -> Option<HttpRequest> {
+    let head_end = block.windows(4).position(|window| window == b"\r\n\r\n").unwrap_or(block.len());
+    let head = String::from_utf8_lossy(&block[..head_end]);
+    let mut lines = head.split("\r\n");
+
+    let method = lines.next()?.split(' ').next().filter(|method| !method.is_empty())?.to_string();
+    let headers = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect();
+
+    Some(HttpRequest { method, headers })
+}
+
+/// An HTTP response as archived in a WARC response record
+#[derive(Debug)]
+pub struct HttpResponse {
+    pub http_version: String,
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+/// Parse the HTTP response in the block of a WARC response record
+pub fn parse_http_response(block: &[u8]) -> Option<HttpResponse> {
+    let head_end = block.windows(4).position(|window| window == b"\r\n\r\n")?;
+    let head = String::from_utf8_lossy(&block[..head_end]);
+    let mut lines = head.split("\r\n");
+
+    let mut status_line = lines.next()?.splitn(3, ' ');
+    let http_version = status_line.next()?.to_string();
+    let status = status_line.next()?.parse().ok()?;
+
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect();
+
+    let body = &block[head_end + 4..];
+    let chunked = headers
+        .iter()
+        .any(|(name, value)| name.eq_ignore_ascii_case("transfer-encoding") && value.eq_ignore_ascii_case("chunked"));
+
+    Some(HttpResponse {
+        http_version,
+        status,
+        body: if chunked { dechunk(body) } else { body.to_vec() },
+        headers,
+    })
+}
+
+/// Decode a chunked transfer encoded body, keeping whatever could be decoded if it is malformed
+fn dechunk(mut body: &[u8]) -> Vec<u8> {
+    let mut decoded = Vec::new();
+
+    while let Some(line_end) = body.windows(2).position(|window| window == b"\r\n") {
+        let size = String::from_utf8_lossy(&body[..line_end]);
+        let Ok(size) = usize::from_str_radix(size.split(';').next().unwrap_or_default().trim(), 16) else { break; };
+        if size == 0 {
+            break;
+        }
+
+        let chunk_start = line_end + 2;
+        let chunk_end = (chunk_start + size).min(body.len());
+        decoded.extend_from_slice(&body[chunk_start..chunk_end]);
+        body = body.get(chunk_end + 2..).unwrap_or_default();
+    }
+
+    decoded
+}
+
+fn record_id() -> String {
+    format!("<urn:uuid:{}>", Uuid::new_v4())
+}
+
+/// Rebuilds the HTTP request that was sent for a fetched URL
+fn http_request(fetched: &FetchedUrl) -> Vec<u8> {
+    let target = Url::parse(&fetched.url)
+        .map(|url| match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        })
+        .unwrap_or_else(|_| "/".to_string());
+
+    let mut request = format!("{} {} {}\r\n", fetched.method, target, fetched.http_version);
+    for (name, value) in &fetched.request_headers {
+        request.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    request.push_str("\r\n");
+
+    request.into_bytes()
+}
+
+/// Rebuilds the HTTP response that was received for a fetched URL
+fn http_response(fetched: &FetchedUrl) -> Vec<u8> {
+    let reason = StatusCode::from_u16(fetched.status)
+        .ok()
+        .and_then(|status| status.canonical_reason())
+        .unwrap_or_default();
+
+    let mut response = format!("{} {} {}\r\n", fetched.http_version, fetched.status, reason);
+    for (name, value) in &fetched.headers {
+        // The body is stored de-chunked, so the framing headers have to describe it as such for replay
+        if name.eq_ignore_ascii_case("transfer-encoding") || name.eq_ignore_ascii_case("content-length") {
+            continue;
+        }
+        response.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    response.push_str(&format!("Content-Length: {}\r\n\r\n", fetched.body.len()));
+
+    let mut response = response.into_bytes();
+    response.extend_from_slice(&fetched.body);
+    response
+}
+
+/// Crawler metadata about a fetched URL: timings, outlinks and findings
+fn metadata_fields(fetched: &FetchedUrl) -> Vec<u8> {
+    let mut fields = format!("fetchTimeMs: {}\r\n", fetched.duration_ms);
+    for link in &fetched.links {
+        fields.push_str(&format!("outlink: {}\r\n", link));
+    }
+    for finding in &fetched.findings {
+        fields.push_str(&format!("finding: {} {}\r\n", finding.kind, finding.detail.replace(['\r', '\n'], " ")));
+    }
+
+    fields.into_bytes()
+}
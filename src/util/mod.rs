@@ -1,3 +1,5 @@
+pub mod redact;
+
 use tokio::sync::mpsc;
 
 #[derive(Debug)]
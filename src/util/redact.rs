@@ -0,0 +1,48 @@
+use std::collections::BTreeMap;
+
+use serde::Serializer;
+
+/// What secrets are replaced with when settings are stored with a session or printed
+pub const REDACTED: &str = "<redacted>";
+
+/// Request headers whose values are credentials
+const SENSITIVE_HEADERS: [&str; 5] = ["authorization", "proxy-authorization", "cookie", "x-api-key", "x-auth-token"];
+
+/// Whether the value of a header is a credential.
+pub fn is_sensitive_header(name: &str) -> bool {
+    SENSITIVE_HEADERS.iter().any(|sensitive| name.trim().eq_ignore_ascii_case(sensitive))
+}
+
+/// Returns a header with its value replaced by the placeholder if it is a credential.
+pub fn header((name, value): (String, String)) -> (String, String) {
+    let value = if is_sensitive_header(&name) { REDACTED.to_string() } else { value };
+    (name, value)
+}
+
+/// Serializes a secret as the placeholder.
+pub fn secret<T, S: Serializer>(_: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(REDACTED)
+}
+
+/// Serializes an optional secret as the placeholder, if there is one.
+pub fn optional_secret<T, S: Serializer>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error> {
+    match value {
+        Some(_) => serializer.serialize_str(REDACTED),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Serializes optional headers with the values of the credentials among them replaced by the placeholder.
+pub fn header_values<S: Serializer>(headers: &Option<BTreeMap<String, String>>, serializer: S) -> Result<S::Ok, S::Error> {
+    match headers {
+        Some(headers) => serializer.collect_map(
+            headers.iter().map(|(name, value)| (name, if is_sensitive_header(name) { REDACTED } else { value.as_str() })),
+        ),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Serializes a map of secrets with every value replaced by the placeholder.
+pub fn secret_values<T, S: Serializer>(map: &BTreeMap<String, T>, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(map.keys().map(|key| (key, REDACTED)))
+}
@@ -0,0 +1,29 @@
+use reqwest::RequestBuilder;
+use serde::{Deserialize, Serialize};
+
+use crate::util::redact;
+
+/// Credentials sent with every request to a host. The secrets are redacted when serialized.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+pub enum Credentials {
+    Basic {
+        username: String,
+        #[serde(serialize_with = "redact::optional_secret")]
+        password: Option<String>,
+    },
+    Bearer {
+        #[serde(serialize_with = "redact::secret")]
+        token: String,
+    },
+}
+
+impl Credentials {
+    /// Adds the Authorization header to a request.
+    pub fn apply(&self, request: RequestBuilder) -> RequestBuilder {
+        match self {
+            Credentials::Basic { username, password } => request.basic_auth(username, password.as_ref()),
+            Credentials::Bearer { token } => request.bearer_auth(token),
+        }
+    }
+}
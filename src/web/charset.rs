@@ -0,0 +1,68 @@
+use std::sync::OnceLock;
+
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252, X_USER_DEFINED};
+use regex::bytes::Regex;
+use url::Url;
+
+/// How far into an HTML document a `<meta>` charset declaration is looked for, as browsers do
+const META_PRESCAN_LENGTH: usize = 1024;
+
+/// Detects the encoding of a body, from the first of: its byte order mark, the charset of its content type, a
+/// `<meta>` declaration if it is HTML, and sniffing its content with the TLD of its URL as a hint.
+pub fn detect(body: &[u8], content_type: &str, url: &Url) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(body) {
+        return encoding;
+    }
+
+    if let Some(encoding) = content_type_charset(content_type) {
+        return encoding;
+    }
+
+    let mime_type = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+    if mime_type.is_empty() || mime_type == "text/html" || mime_type == "application/xhtml+xml" {
+        if let Some(encoding) = meta_charset(body) {
+            return encoding;
+        }
+    }
+
+    let mut detector = EncodingDetector::new();
+    detector.feed(body, true);
+    let tld = url.host_str().and_then(|host| host.rsplit('.').next()).map(str::as_bytes);
+    detector.guess(tld, true)
+}
+
+/// Decodes a body to UTF-8 text, detecting its encoding. Returns the text and the encoding it was decoded from.
+pub fn decode(body: &[u8], content_type: &str, url: &Url) -> (String, &'static Encoding) {
+    let encoding = detect(body, content_type, url);
+    (encoding.decode_with_bom_removal(body).0.into_owned(), encoding)
+}
+
+/// Decodes a stored body with the encoding it was detected in, UTF-8 if unknown or unrecognised.
+pub fn decode_stored(body: &[u8], encoding: Option<&str>) -> String {
+    let encoding = encoding.and_then(|label| Encoding::for_label(label.as_bytes())).unwrap_or(UTF_8);
+    encoding.decode_with_bom_removal(body).0.into_owned()
+}
+
+/// The encoding named by the charset parameter of a content type
+fn content_type_charset(content_type: &str) -> Option<&'static Encoding> {
+    content_type
+        .split(';')
+        .skip(1)
+        .filter_map(|parameter| parameter.split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("charset"))
+        .and_then(|(_, charset)| Encoding::for_label(charset.trim().trim_matches(['"', '\'']).as_bytes()))
+}
+
+/// The encoding declared by a `<meta charset>` or `<meta http-equiv="Content-Type">` at the start of an HTML document
+fn meta_charset(body: &[u8]) -> Option<&'static Encoding> {
+    static META: OnceLock<Regex> = OnceLock::new();
+    let meta = META.get_or_init(|| Regex::new(r#"(?i-u)<meta\s[^>]*?charset\s*=\s*["']?\s*([a-z0-9_:.+-]+)"#).unwrap());
+
+    let prefix = &body[..body.len().min(META_PRESCAN_LENGTH)];
+    let label = meta.captures(prefix)?.get(1)?.as_bytes();
+
+    // A document that can declare its encoding in ASCII is not UTF-16, whatever it says
+    let encoding = Encoding::for_label(label)?;
+    Some(if encoding == X_USER_DEFINED { WINDOWS_1252 } else { encoding.output_encoding() })
+}
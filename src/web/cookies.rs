@@ -0,0 +1,60 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use reqwest::cookie::Jar;
+use url::Url;
+
+/// Adds the cookies of a Netscape cookies file, as exported by browsers and curl, to a cookie jar.
+///
+/// Returns how many cookies were added. Expired cookies and malformed lines are skipped.
+pub fn load(path: &Path, jar: &Jar) -> io::Result<usize> {
+    let now = chrono::Utc::now().timestamp();
+    let mut added = 0;
+
+    for line in fs::read_to_string(path)?.lines() {
+        // curl marks HttpOnly cookies with a prefix that otherwise reads as a comment
+        let (line, http_only) = match line.strip_prefix("#HttpOnly_") {
+            Some(line) => (line, true),
+            None => (line, false),
+        };
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split('\t').collect();
+        let [domain, include_subdomains, cookie_path, secure, expires, name, value] = fields[..] else {
+            tracing::warn!("Skipping malformed line in {}: {}", path.display(), line);
+            continue;
+        };
+
+        // Session cookies have no expiry
+        let expires: i64 = expires.trim().parse().unwrap_or_default();
+        if expires != 0 && expires <= now {
+            continue;
+        }
+
+        let secure = secure.eq_ignore_ascii_case("TRUE");
+        let host = domain.trim_start_matches('.');
+        let Ok(url) = Url::parse(&format!("{}://{}{}", if secure { "https" } else { "http" }, host, cookie_path)) else { continue; };
+
+        let mut cookie = format!("{}={}; Path={}", name, value, cookie_path);
+        if include_subdomains.eq_ignore_ascii_case("TRUE") {
+            cookie.push_str(&format!("; Domain={}", host));
+        }
+        if expires != 0 {
+            cookie.push_str(&format!("; Max-Age={}", expires - now));
+        }
+        if secure {
+            cookie.push_str("; Secure");
+        }
+        if http_only {
+            cookie.push_str("; HttpOnly");
+        }
+
+        jar.add_cookie_str(&cookie, &url);
+        added += 1;
+    }
+
+    Ok(added)
+}
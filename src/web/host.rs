@@ -1,4 +1,4 @@
-use std::net::{Ipv4Addr, Ipv6Addr};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::fmt;
 
 use crate::dns::domain_name::{DomainName, DomainNameParseError};
@@ -67,6 +67,15 @@ impl TryFrom<url::Host<&str>> for Host {
     }
 }
 
+impl From<IpAddr> for Host {
+    fn from(value: IpAddr) -> Self {
+        match value {
+            IpAddr::V4(ip) => Host::Ipv4(ip),
+            IpAddr::V6(ip) => Host::Ipv6(ip)
+        }
+    }
+}
+
 impl fmt::Display for Host {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
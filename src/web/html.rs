@@ -0,0 +1,38 @@
+use scraper::{Html, Node, Selector};
+
+/// Elements whose text is not part of the readable page text. The title is kept separately.
+const NON_CONTENT_ELEMENTS: [&str; 5] = ["title", "script", "style", "noscript", "template"];
+
+/// The readable content of an HTML page
+#[derive(Debug, Clone, Default)]
+pub struct PageText {
+    pub title: Option<String>,
+    pub text: String,
+}
+
+/// Extract the title and the tag-stripped text of an HTML document
+pub fn page_text(document: &Html) -> PageText {
+    let title_selector = Selector::parse("title").unwrap();
+    let title = document
+        .select(&title_selector)
+        .next()
+        .map(|title| title.text().collect::<Vec<_>>().join(" ").trim().to_string())
+        .filter(|title| !title.is_empty());
+
+    let mut text: Vec<&str> = Vec::new();
+    for node in document.root_element().descendants() {
+        let Node::Text(node_text) = node.value() else { continue; };
+
+        let is_content = node.ancestors().all(|ancestor| match ancestor.value() {
+            Node::Element(element) => !NON_CONTENT_ELEMENTS.contains(&element.name()),
+            _ => true,
+        });
+
+        let node_text = node_text.trim();
+        if is_content && !node_text.is_empty() {
+            text.push(node_text);
+        }
+    }
+
+    PageText { title, text: text.join(" ") }
+}
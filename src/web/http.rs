@@ -1,23 +1,217 @@
-use reqwest::{header::{HeaderMap, HeaderValue}, Client, Response};
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+use reqwest::{header::{HeaderMap, HeaderValue, CONTENT_LENGTH, RANGE}, Client, Response, StatusCode};
 use url::Url;
 
+use super::{auth::Credentials, tls};
+
+/// The User-Agent the crawler identifies itself with
+pub const USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// File extensions of resources that are seldom textual, and often large
+const BINARY_EXTENSIONS: &[&str] = &[
+    "7z", "apk", "avi", "bin", "bmp", "bz2", "dmg", "doc", "docx", "eot", "exe", "flac", "gif", "gz", "ico", "img",
+    "iso", "jar", "jpeg", "jpg", "m4a", "m4v", "mkv", "mov", "mp3", "mp4", "mpeg", "msi", "ogg", "otf", "pdf", "png",
+    "ppt", "pptx", "rar", "tar", "tgz", "tif", "tiff", "ttf", "wav", "webm", "webp", "woff", "woff2", "xls", "xlsx",
+    "xz", "zip",
+];
 
-/// Perform a HEAD request to the specified URL
-pub async fn head_url(client: &Client, url: Url) -> Result<Response, reqwest::Error> {
-    client.head(url).send().await
+/// Perform a HEAD request to the specified URL, with the credentials of its host if there are any
+pub async fn head_url(client: &Client, url: Url, credentials: Option<&Credentials>) -> Result<Response, reqwest::Error> {
+    let request = client.head(url);
+    match credentials {
+        Some(credentials) => credentials.apply(request),
+        None => request,
+    }
+    .send()
+    .await
 }
 
-/// Perform an asynchrnonous GET request to the specified URL
-pub async fn get_url(client: &Client, url: Url) -> Result<Response, reqwest::Error >{
-    client.get(url).send().await
+/// Perform an asynchrnonous GET request to the specified URL, with the credentials of its host if there are any
+pub async fn get_url(client: &Client, url: Url, credentials: Option<&Credentials>) -> Result<Response, reqwest::Error >{
+    let request = client.get(url);
+    match credentials {
+        Some(credentials) => credentials.apply(request),
+        None => request,
+    }
+    .send()
+    .await
+}
+
+/// Perform a GET request for the first bytes of the specified URL, with the credentials of its host if there are any
+pub async fn get_url_range(client: &Client, url: Url, credentials: Option<&Credentials>, length: usize) -> Result<Response, reqwest::Error> {
+    let request = client.get(url).header(RANGE, format!("bytes=0-{}", length.saturating_sub(1)));
+    match credentials {
+        Some(credentials) => credentials.apply(request),
+        None => request,
+    }
+    .send()
+    .await
 }
 
 /// Obtain the headers of the response to a GET request
+#[allow(dead_code)]
 pub async fn get_url_response_headers(client: &Client, url: Url) -> Result<HeaderMap<HeaderValue>, reqwest::Error> {
-    match (client.head(url).send().await) {
+    match client.head(url).send().await {
         Ok(response) => {
             Ok(response.headers().to_owned())
         },
         Err(error) => Err(error)
     }
-}
\ No newline at end of file
+}
+/// The headers sent with a GET request to the specified URL
+pub fn request_headers(url: &Url) -> Vec<(String, String)> {
+    let host = match (url.host_str(), url.port()) {
+        (Some(host), Some(port)) => format!("{}:{}", host, port),
+        (Some(host), None) => host.to_string(),
+        (None, _) => String::new(),
+    };
+
+    vec![
+        ("host".to_string(), host),
+        ("user-agent".to_string(), USER_AGENT.to_string()),
+        ("accept".to_string(), "*/*".to_string()),
+    ]
+}
+
+/// Whether a response with the given content type has a textual body worth downloading
+pub fn is_textual(content_type: &str) -> bool {
+    let mime_type = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+
+    mime_type.starts_with("text/")
+        || mime_type.ends_with("+xml")
+        || mime_type.ends_with("+json")
+        || ["application/json", "application/xml", "application/javascript", "application/x-javascript"].contains(&mime_type.as_str())
+}
+
+/// Whether the extension of a URL's path suggests a binary resource, which is worth a HEAD request before a GET
+pub fn is_likely_binary(url: &Url) -> bool {
+    let file_name = url.path().rsplit('/').next().unwrap_or_default();
+    match file_name.rsplit_once('.') {
+        Some((_, extension)) => BINARY_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str()),
+        None => false,
+    }
+}
+
+/// The length of a body as announced by the Content-Length header, which a HEAD response also carries
+pub fn content_length(headers: &HeaderMap) -> Option<u64> {
+    headers.get(CONTENT_LENGTH)?.to_str().ok()?.trim().parse().ok()
+}
+
+/// Whether a response with the given status is worth asking for again, as it is usually a passing failure of a gateway
+pub fn is_transient_status(status: StatusCode) -> bool {
+    matches!(status, StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT)
+}
+
+/// How long each step of a fetch may take
+#[derive(Debug, Clone, Copy)]
+pub struct Timeouts {
+    /// Until the connection is established, including the TLS handshake
+    pub connect: Duration,
+    /// Between receiving anything from the server, once connected
+    pub read: Duration,
+    /// For the whole exchange, including the body
+    pub total: Duration,
+}
+
+/// Why a fetch failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FetchError {
+    /// A timeout expired before the exchange was done
+    Timeout,
+    /// The host name did not resolve
+    Dns,
+    /// Nothing listens on the port
+    ConnectionRefused,
+    /// The server or a middlebox dropped the connection
+    ConnectionReset,
+    /// The connection failed otherwise, such as through a proxy
+    Connect,
+    /// The TLS handshake failed
+    Tls,
+    /// The redirects could not be followed
+    Redirect,
+    /// The body could not be downloaded or decoded
+    Body,
+    /// Any other failure, such as an invalid request
+    Request,
+}
+
+impl FetchError {
+    /// Classifies a request error.
+    pub fn classify(error: &reqwest::Error) -> FetchError {
+        if error.is_timeout() || io_error_kind(error) == Some(io::ErrorKind::TimedOut) {
+            FetchError::Timeout
+        } else if tls::is_tls_error(error) {
+            FetchError::Tls
+        } else if io_error_kind(error) == Some(io::ErrorKind::ConnectionRefused) {
+            FetchError::ConnectionRefused
+        } else if matches!(
+            io_error_kind(error),
+            Some(io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted | io::ErrorKind::BrokenPipe | io::ErrorKind::UnexpectedEof)
+        ) {
+            FetchError::ConnectionReset
+        } else if is_dns_error(error) {
+            FetchError::Dns
+        } else if error.is_connect() {
+            FetchError::Connect
+        } else if error.is_redirect() {
+            FetchError::Redirect
+        } else if error.is_body() || error.is_decode() {
+            FetchError::Body
+        } else {
+            FetchError::Request
+        }
+    }
+
+    /// Whether the same request may well succeed if sent again.
+    pub fn is_transient(self) -> bool {
+        matches!(self, FetchError::Timeout | FetchError::ConnectionReset)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FetchError::Timeout => "timeout",
+            FetchError::Dns => "dns",
+            FetchError::ConnectionRefused => "connection-refused",
+            FetchError::ConnectionReset => "connection-reset",
+            FetchError::Connect => "connect",
+            FetchError::Tls => "tls",
+            FetchError::Redirect => "redirect",
+            FetchError::Body => "body",
+            FetchError::Request => "request",
+        }
+    }
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// The kind of the first IO error in the source chain of an error.
+fn io_error_kind(error: &(dyn std::error::Error + 'static)) -> Option<io::ErrorKind> {
+    let mut source = Some(error);
+    while let Some(error) = source {
+        if let Some(io_error) = error.downcast_ref::<io::Error>() {
+            return Some(io_error.kind());
+        }
+        source = error.source();
+    }
+    None
+}
+
+/// Whether an error comes from resolving a host name. The connector only tells it apart by its message.
+fn is_dns_error(error: &(dyn std::error::Error + 'static)) -> bool {
+    let mut source = Some(error);
+    while let Some(error) = source {
+        if error.to_string().starts_with("dns error") {
+            return true;
+        }
+        source = error.source();
+    }
+    false
+}
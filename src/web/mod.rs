@@ -1,2 +1,7 @@
+pub mod auth;
+pub mod charset;
+pub mod cookies;
 pub mod http;
-pub mod host;
\ No newline at end of file
+pub mod host;
+pub mod html;
+pub mod tls;
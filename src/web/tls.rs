@@ -0,0 +1,283 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier},
+    Certificate, ClientConfig, OwnedTrustAnchor, PrivateKey, RootCertStore, ServerName,
+};
+use sha2::{Digest, Sha256};
+use x509_parser::{extensions::GeneralName, prelude::*};
+
+/// How the web client establishes TLS connections
+#[derive(Debug, Default)]
+pub struct TlsOptions {
+    /// Connect even when the certificate does not verify, such as when it is self-signed or expired
+    pub accept_invalid_certs: bool,
+    /// PEM certificates trusted in addition to the built-in roots
+    pub ca_bundle: Option<PathBuf>,
+    /// A PEM certificate chain to present to servers that ask for one
+    pub client_cert: Option<PathBuf>,
+    /// The PEM private key of the client certificate, if not in the same file
+    pub client_key: Option<PathBuf>,
+}
+
+/// A certificate chain as a server presented it
+#[derive(Debug, Clone)]
+pub struct PresentedChain {
+    pub certificates: Vec<Vec<u8>>, // DER, the server's own certificate first
+    pub error: Option<String>,      // Why the chain did not verify, if it did not
+}
+
+/// The certificate chains presented to the web client, by host name, and the services whose chain was taken.
+///
+/// The verifier only learns the host name, so a service is matched to its chain by the server certificate the
+/// connection ended up with. Different ports of a host may present different certificates.
+#[derive(Debug, Default)]
+pub struct PresentedChains {
+    presented: Mutex<HashMap<String, Vec<PresentedChain>>>,
+    taken: Mutex<HashSet<(String, u16)>>,
+}
+
+impl PresentedChains {
+    fn present(&self, host: String, chain: PresentedChain) {
+        let mut presented = self.presented.lock().unwrap();
+        let chains = presented.entry(host).or_default();
+        if !chains.iter().any(|presented| presented.certificates.first() == chain.certificates.first()) {
+            chains.push(chain);
+        }
+    }
+
+    /// Returns the chain the service at a host and port presented, the first time it is asked for after it
+    /// presented one. The service is recognised by its server certificate, or, if that is unknown such as when the
+    /// handshake failed, by being the only chain the host presented.
+    pub fn take(&self, host: &str, port: u16, server_certificate: Option<&[u8]>) -> Option<PresentedChain> {
+        let mut taken = self.taken.lock().unwrap();
+        if taken.contains(&(host.to_string(), port)) {
+            return None;
+        }
+
+        let presented = self.presented.lock().unwrap();
+        let chains = presented.get(host)?;
+        let chain = match server_certificate {
+            Some(certificate) => chains.iter().find(|chain| chain.certificates.first().map(Vec::as_slice) == Some(certificate))?,
+            None if chains.len() == 1 => &chains[0],
+            None => return None,
+        };
+
+        taken.insert((host.to_string(), port));
+        Some(chain.clone())
+    }
+}
+
+/// Verifies certificates like any client would, keeping each chain presented
+struct RecordingVerifier {
+    verifier: WebPkiVerifier,
+    accept_invalid_certs: bool,
+    chains: Arc<PresentedChains>,
+}
+
+impl ServerCertVerifier for RecordingVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.verifier.verify_server_cert(end_entity, intermediates, server_name, scts, ocsp_response, now);
+
+        let host = match server_name {
+            ServerName::DnsName(name) => name.as_ref().to_ascii_lowercase(),
+            ServerName::IpAddress(ip) => ip.to_string(),
+            _ => String::new(),
+        };
+        let chain = PresentedChain {
+            certificates: std::iter::once(end_entity).chain(intermediates).map(|certificate| certificate.0.clone()).collect(),
+            error: verified.as_ref().err().map(ToString::to_string),
+        };
+        self.chains.present(host, chain);
+
+        match verified {
+            Err(_) if self.accept_invalid_certs => Ok(ServerCertVerified::assertion()),
+            verified => verified,
+        }
+    }
+}
+
+/// Builds the TLS configuration of the web client, which hands every presented certificate chain to `chains`.
+pub fn client_config(options: &TlsOptions, chains: Arc<PresentedChains>) -> Result<ClientConfig, TlsError> {
+    let mut roots = RootCertStore::empty();
+    roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|anchor| {
+        OwnedTrustAnchor::from_subject_spki_name_constraints(anchor.subject, anchor.spki, anchor.name_constraints)
+    }));
+
+    if let Some(ca_bundle) = &options.ca_bundle {
+        let certificates = read_certificates(ca_bundle)?;
+        if certificates.is_empty() {
+            return Err(TlsError::new(ca_bundle, "no certificates found"));
+        }
+        for certificate in certificates {
+            roots.add(&certificate).map_err(|error| TlsError::new(ca_bundle, &error.to_string()))?;
+        }
+    }
+
+    let verifier = RecordingVerifier {
+        verifier: WebPkiVerifier::new(roots, None),
+        accept_invalid_certs: options.accept_invalid_certs,
+        chains,
+    };
+    let builder = ClientConfig::builder().with_safe_defaults().with_custom_certificate_verifier(Arc::new(verifier));
+
+    match &options.client_cert {
+        Some(client_cert) => {
+            let chain = read_certificates(client_cert)?;
+            let key_path = options.client_key.as_ref().unwrap_or(client_cert);
+            let key = read_private_key(key_path)?;
+            builder.with_client_auth_cert(chain, key).map_err(|error| TlsError::new(client_cert, &error.to_string()))
+        }
+        None => Ok(builder.with_no_client_auth()),
+    }
+}
+
+fn read_certificates(path: &Path) -> Result<Vec<Certificate>, TlsError> {
+    let file = File::open(path).map_err(|error| TlsError::new(path, &error.to_string()))?;
+    let certificates = rustls_pemfile::certs(&mut BufReader::new(file)).map_err(|error| TlsError::new(path, &error.to_string()))?;
+    Ok(certificates.into_iter().map(Certificate).collect())
+}
+
+fn read_private_key(path: &Path) -> Result<PrivateKey, TlsError> {
+    let file = File::open(path).map_err(|error| TlsError::new(path, &error.to_string()))?;
+    let items = rustls_pemfile::read_all(&mut BufReader::new(file)).map_err(|error| TlsError::new(path, &error.to_string()))?;
+
+    items
+        .into_iter()
+        .find_map(|item| match item {
+            rustls_pemfile::Item::PKCS8Key(key) | rustls_pemfile::Item::RSAKey(key) | rustls_pemfile::Item::ECKey(key) => Some(PrivateKey(key)),
+            _ => None,
+        })
+        .ok_or_else(|| TlsError::new(path, "no private key found"))
+}
+
+/// What a certificate says about its subject
+#[derive(Debug, Clone, Default)]
+pub struct CertificateInfo {
+    pub subject: String,
+    pub common_name: Option<String>,
+    pub issuer: String,
+    pub serial: String,
+    pub not_before: String,
+    pub not_after: String,
+    pub subject_alt_names: Vec<String>, // DNS names and IP addresses
+    pub sha256: String,
+    pub der: Vec<u8>,
+}
+
+impl CertificateInfo {
+    /// The host names the certificate is valid for: its subject alternative names and common name, without wildcards.
+    pub fn host_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        for name in self.subject_alt_names.iter().chain(&self.common_name) {
+            let name = name.trim_start_matches("*.").to_ascii_lowercase();
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        names
+    }
+
+    /// Parses a DER certificate. Only the fingerprint is known of certificates that do not parse.
+    pub fn parse(der: &[u8]) -> CertificateInfo {
+        let sha256 = format!("{:x}", Sha256::digest(der));
+        let Ok((_, certificate)) = X509Certificate::from_der(der) else {
+            return CertificateInfo { sha256, der: der.to_vec(), ..CertificateInfo::default() };
+        };
+
+        let subject_alt_names = match certificate.subject_alternative_name() {
+            Ok(Some(extension)) => extension
+                .value
+                .general_names
+                .iter()
+                .filter_map(|name| match name {
+                    GeneralName::DNSName(name) => Some(name.to_string()),
+                    GeneralName::IPAddress(bytes) => ip_address(bytes).map(|ip| ip.to_string()),
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        let common_name = certificate.subject().iter_common_name().next().and_then(|name| name.as_str().ok()).map(String::from);
+
+        CertificateInfo {
+            subject: certificate.subject().to_string(),
+            common_name,
+            issuer: certificate.issuer().to_string(),
+            serial: certificate.raw_serial_as_string(),
+            not_before: timestamp(certificate.validity().not_before),
+            not_after: timestamp(certificate.validity().not_after),
+            subject_alt_names,
+            sha256,
+            der: der.to_vec(),
+        }
+    }
+}
+
+fn ip_address(bytes: &[u8]) -> Option<IpAddr> {
+    match bytes.len() {
+        4 => Some(IpAddr::from(<[u8; 4]>::try_from(bytes).ok()?)),
+        16 => Some(IpAddr::from(<[u8; 16]>::try_from(bytes).ok()?)),
+        _ => None,
+    }
+}
+
+fn timestamp(time: ASN1Time) -> String {
+    chrono::DateTime::from_timestamp(time.timestamp(), 0)
+        .map(|time| time.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+        .unwrap_or_default()
+}
+
+/// Whether an error was caused by a failed TLS handshake.
+pub fn is_tls_error(error: &(dyn std::error::Error + 'static)) -> bool {
+    let mut source = Some(error);
+    while let Some(error) = source {
+        if error.is::<rustls::Error>() {
+            return true;
+        }
+
+        // IO errors hide the error they wrap from the source chain, and can be nested
+        source = match error.downcast_ref::<io::Error>().and_then(io::Error::get_ref) {
+            Some(wrapped) => Some(wrapped as &(dyn std::error::Error + 'static)),
+            None => error.source(),
+        };
+    }
+    false
+}
+
+#[derive(Debug)]
+pub struct TlsError {
+    message: String,
+}
+
+impl TlsError {
+    fn new(path: &Path, reason: &str) -> TlsError {
+        TlsError {
+            message: format!("Invalid TLS file {}: {}", path.display(), reason),
+        }
+    }
+}
+
+impl std::error::Error for TlsError {}
+
+impl fmt::Display for TlsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}